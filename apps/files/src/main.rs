@@ -3,43 +3,93 @@
     windows_subsystem = "windows"
 )]
 
-use std::{env, path::PathBuf};
+use std::{env, path::PathBuf, time::SystemTime};
 
 use config::ty::App;
 use freya::prelude::*;
 use freya::elements::rect::rect;
 use index::ty::SearchResult;
 
+#[derive(Clone)]
 enum ItemType {
     File,
     Folder
 }
 
+#[derive(Clone)]
 struct Item {
     ty: ItemType,
     name: String,
-    path: String
+    path: String,
+    size: u64,
+    modified: i64,
+    mime: Option<String>,
 }
 
 impl From<SearchResult> for Item {
     fn from(value: SearchResult) -> Self {
         Item {
-            ty: ItemType::File,
+            ty: if value.is_dir { ItemType::Folder } else { ItemType::File },
             name: value.name,
             path: value.path,
+            size: value.metadata.size,
+            modified: value.metadata.modified,
+            mime: value.metadata.mime,
         }
     }
 }
 
+impl Item {
+    /// Opens it with its default application, which the index ranks higher for.
+    fn open(&self) {
+        if let Err(e) = index::launch::open(self.path.as_ref()) {
+            eprintln!("Failed to open {}: {e}", self.path);
+        }
+    }
+
+    /// Its size in the largest unit it has at least one of, blank for folders.
+    fn size(&self) -> String {
+        if let ItemType::Folder = self.ty {
+            return String::new();
+        }
+
+        let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut size = self.size as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < units.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+        match unit {
+            0 => format!("{} B", self.size),
+            _ => format!("{size:.1} {}", units[unit]),
+        }
+    }
+
+    /// How long ago it was modified, in the largest unit it's been at least one of.
+    fn age(&self) -> String {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64);
+        let seconds = (now - self.modified).max(0);
+        let units = [(365 * 24 * 60 * 60, "y"), (7 * 24 * 60 * 60, "w"), (24 * 60 * 60, "d"), (60 * 60, "h"), (60, "m")];
+        let (count, unit) = units
+            .into_iter()
+            .find(|(unit, _)| seconds >= *unit)
+            .map_or((seconds, "s"), |(unit, name)| (seconds / unit, name));
+        format!("{count}{unit} ago")
+    }
+}
+
 fn fetch(p: PathBuf, s: &mut State<Vec<Item>>) {
     let mut w = s.write();
     w.clear();
 
     let _ = ipsea::send_command(
         App::IndexService,
-        &index::ty::Request { query: p.to_str().unwrap_or_default().to_owned() },
-        Some(move |value: index::ty::SearchResult| {
-            w.push(value.into());
+        &index::ty::Request::Search { query: p.to_str().unwrap_or_default().to_owned(), scope: index::ty::Scope::Names },
+        Some(move |response: index::ty::Response| {
+            if let index::ty::Response::Result(value) = response {
+                w.push(value.into());
+            }
     }));
 }
 
@@ -54,11 +104,27 @@ fn app() -> impl IntoElement {
     .child(rect().child("Left").height(Size::fill()).width(Size::percent(*fr.read())))
     .child(rect().child("Right").height(Size::fill()).width(Size::percent(1.0 - *fr.read())));
 
+    let listing = items
+    .read()
+    .iter()
+    .cloned()
+    .map(|item| {
+        rect()
+            .horizontal()
+            .width(Size::fill())
+            .child(rect().width(Size::percent(55.0)).child(item.name.clone()))
+            .child(rect().width(Size::percent(15.0)).child(item.size()))
+            .child(rect().width(Size::percent(10.0)).child(item.age()))
+            .child(rect().width(Size::percent(20.0)).child(item.mime.clone().unwrap_or_default()))
+            .on_press(move |_| item.open())
+    })
+    .fold(rect().background(Color::BLACK), |listing, row| listing.child(row));
+
     let content = rect()
     .width(Size::fill())
     .height(Size::fill())
     .child(rect().background(Color::BLACK).child("Left").height(Size::fill()).width(Size::percent(*fr.read())))
-    .child(rect().background(Color::BLACK).child("Right").height(Size::fill()).width(Size::percent(1.0 - *fr.read())));
+    .child(listing.height(Size::fill()).width(Size::percent(1.0 - *fr.read())));
 
     rect()
         // .width(Size::fill())
@@ -0,0 +1,310 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{stdin, stdout, ErrorKind, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    process::{self, Command},
+};
+
+use anyhow::{anyhow, Result};
+use clap::Subcommand;
+use config::{ty::App, Format, Validate};
+use index::settings::IndexConfig;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+
+/// Apps with a config file, checked when none is named.
+const APPS: [App; 3] = [App::Scan, App::Files, App::IndexService];
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    #[command(about = "Check configs for errors without starting anything")]
+    Check {
+        #[arg(help = "App to check, defaults to all of them")]
+        app: Option<App>,
+    },
+    #[command(about = "Print the path of an app's config file")]
+    Path { app: App },
+    #[command(about = "Print every resolved value, and where it came from")]
+    Show { app: App },
+    #[command(about = "Print the resolved value of a dotted key, e.g. `roots.0.path`")]
+    Get { app: App, key: String },
+    #[command(about = "Set a dotted key in the user's config file")]
+    Set {
+        app: App,
+        key: String,
+        #[arg(help = "Parsed as JSON, falling back to a plain string")]
+        value: String,
+    },
+    #[command(about = "Remove a dotted key from the user's config file, reverting to the default")]
+    Unset { app: App, key: String },
+    #[command(about = "Edit the user's config file in $EDITOR, saving it only once valid")]
+    Edit { app: App },
+}
+
+impl ConfigCommand {
+    fn app(&self) -> Option<&App> {
+        match self {
+            ConfigCommand::Check { app } => app.as_ref(),
+            ConfigCommand::Path { app }
+            | ConfigCommand::Show { app }
+            | ConfigCommand::Get { app, .. }
+            | ConfigCommand::Set { app, .. }
+            | ConfigCommand::Unset { app, .. }
+            | ConfigCommand::Edit { app } => Some(app),
+        }
+    }
+}
+
+pub fn run(command: ConfigCommand) {
+    if let ConfigCommand::Check { app: None } = command {
+        let failed = APPS.into_iter().filter(|app| run_for(ConfigCommand::Check { app: Some(app.clone()) }).is_err());
+        if failed.count() > 0 {
+            process::exit(1);
+        }
+        return;
+    }
+
+    if run_for(command).is_err() {
+        process::exit(1);
+    }
+}
+
+/// Runs `command` against the config type of the app it names.
+fn run_for(command: ConfigCommand) -> Result<()> {
+    let result = match command.app() {
+        Some(App::IndexService) => {
+            index::settings::register_migrations();
+            execute::<IndexConfig>(command)
+        }
+        _ => execute::<Value>(command),
+    };
+
+    if let Err(e) = &result {
+        eprintln!("{e}");
+    }
+    result
+}
+
+fn execute<T: DeserializeOwned + Serialize + Default + Validate>(command: ConfigCommand) -> Result<()> {
+    match command {
+        ConfigCommand::Check { app } => {
+            let app = app.expect("Checks of every app are split up by `run`");
+            config::get_config::<T>(app.clone())?;
+            println!("{app}: ok");
+        }
+        ConfigCommand::Path { app } => println!("{}", config::config_path(&app)?.display()),
+        ConfigCommand::Show { app } => {
+            let layered = config::get_config_layered::<T>(app)?;
+            let value = serde_json::to_value(&layered.value)?;
+            for (key, layer) in &layered.sources {
+                if let Some(v) = lookup(&value, key) {
+                    println!("{key} = {v}  ({layer})");
+                }
+            }
+        }
+        ConfigCommand::Get { app, key } => {
+            let value = serde_json::to_value(config::get_config::<T>(app)?)?;
+            match lookup(&value, &key) {
+                Some(Value::String(s)) => println!("{s}"),
+                Some(v) => println!("{}", serde_json::to_string_pretty(v)?),
+                None => return Err(anyhow!("{key}: not set")),
+            }
+        }
+        ConfigCommand::Set { app, key, value } => {
+            let value = serde_json::from_str(&value).unwrap_or(Value::String(value));
+            let defaults = serde_json::to_value(T::default())?;
+            known(&defaults, &key)?;
+
+            // What the user's file lacks is shaped like what it currently resolves to
+            let resolved = match config::get_config::<T>(app.clone()) {
+                Ok(config) => serde_json::to_value(config)?,
+                Err(_) => defaults,
+            };
+            config::update_user_config::<T>(app, |user| assign(user, &resolved, &key, value))?;
+        }
+        ConfigCommand::Unset { app, key } => config::update_user_config::<T>(app, |user| {
+            if !remove(user, &key) {
+                return Err(anyhow!("{key}: not set in the user config"));
+            }
+            Ok(())
+        })?,
+        ConfigCommand::Edit { app } => edit::<T>(app)?,
+    }
+
+    Ok(())
+}
+
+/// Opens a temp copy of the user's config in `$VISUAL`/`$EDITOR`, only
+/// replacing the real file once the edit parses and validates.
+fn edit<T: DeserializeOwned + Serialize + Default + Validate>(app: App) -> Result<()> {
+    let path = config::config_path(&app)?;
+    let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or(".json".to_string());
+
+    let original = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(_) => Format::from_path(&path).render(&Value::Object(Map::new()), None)?,
+    };
+    let (temp, mut file) = create_temp(&format!("finick-{app}-{}", process::id()), &ext)?;
+    file.write_all(original.as_bytes())?;
+    drop(file);
+
+    let editor = env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or("vi".to_string());
+    let result = loop {
+        let status = Command::new("sh").arg("-c").arg(format!("{editor} \"$1\"")).arg("sh").arg(&temp).status()?;
+        if !status.success() {
+            break Err(anyhow!("{editor} exited with {status}, config left unchanged"));
+        }
+
+        let text = fs::read_to_string(&temp)?;
+        if text == original {
+            println!("No changes");
+            break Ok(());
+        }
+
+        match config::replace_user_config::<T>(app.clone(), &text) {
+            Ok(_) => break Ok(()),
+            Err(e) => {
+                eprintln!("{e}");
+                if !confirm("Edit again?")? {
+                    break Err(anyhow!("Config left unchanged"));
+                }
+            }
+        }
+    };
+
+    let _ = fs::remove_file(&temp);
+    result
+}
+
+/// Creates a new file only the user can read in the temp dir, never opening one
+/// that's already there (or a symlink planted in its place).
+fn create_temp(stem: &str, ext: &str) -> Result<(PathBuf, File)> {
+    for attempt in 0..100 {
+        let path = env::temp_dir().join(format!("{stem}-{attempt}{ext}"));
+        match OpenOptions::new().write(true).create_new(true).mode(0o600).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(anyhow!("{}: {e}", path.display())),
+        }
+    }
+    Err(anyhow!("Failed to create a temp file for {stem}{ext}"))
+}
+
+fn confirm(question: &str) -> Result<bool> {
+    print!("{question} [Y/n] ");
+    stdout().flush()?;
+
+    let mut answer = String::new();
+    stdin().read_line(&mut answer)?;
+    Ok(!answer.trim().eq_ignore_ascii_case("n"))
+}
+
+fn lookup<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.').try_fold(value, |value, segment| match value {
+        Value::Object(map) => map.get(segment),
+        Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Checks every segment of `key` is a field of `defaults`, as far as they say.
+/// Arrays are checked against their first item, and nulls or empty arrays could hold anything.
+fn known(defaults: &Value, key: &str) -> Result<()> {
+    let mut schema = Some(defaults);
+    for segment in key.split('.') {
+        schema = match schema {
+            Some(Value::Object(map)) => Some(map.get(segment).ok_or_else(|| anyhow!("{key}: unknown key `{segment}`"))?),
+            Some(Value::Array(items)) => items.first(),
+            _ => None,
+        };
+    }
+    Ok(())
+}
+
+/// Sets `key` to `new`, creating what's missing on the way: arrays are copied
+/// whole from `resolved`, since a layer replaces them, and the rest start as objects.
+fn assign(value: &mut Value, resolved: &Value, key: &str, new: Value) -> Result<()> {
+    let (parent, last) = match key.rsplit_once('.') {
+        Some((parent, last)) => (Some(parent), last),
+        None => (None, key),
+    };
+
+    let mut target = value;
+    let mut resolved = Some(resolved);
+    for segment in parent.into_iter().flat_map(|p| p.split('.')) {
+        if target.is_null() {
+            *target = match resolved {
+                Some(Value::Array(items)) => Value::Array(items.clone()),
+                _ => Value::Object(Map::new()),
+            };
+        }
+        resolved = resolved.and_then(|r| lookup(r, segment));
+
+        target = match target {
+            Value::Array(items) => {
+                let i = segment.parse::<usize>().map_err(|_| anyhow!("{key}: {segment} isn't an index"))?;
+                if i == items.len() {
+                    items.push(Value::Null);
+                }
+                items.get_mut(i).ok_or_else(|| anyhow!("{key}: no item {segment}"))?
+            }
+            Value::Object(map) => map.entry(segment).or_insert(Value::Null),
+            _ => return Err(anyhow!("{key}: {segment} isn't a table")),
+        };
+    }
+
+    if target.is_null() {
+        if let Some(Value::Array(items)) = resolved {
+            *target = Value::Array(items.clone());
+        }
+    }
+
+    match target {
+        Value::Array(items) => {
+            let i = last.parse::<usize>().map_err(|_| anyhow!("{key}: {last} isn't an index"))?;
+            match i.cmp(&items.len()) {
+                std::cmp::Ordering::Less => items[i] = new,
+                std::cmp::Ordering::Equal => items.push(new),
+                std::cmp::Ordering::Greater => return Err(anyhow!("{key}: no item {i}")),
+            }
+        }
+        Value::Null => *target = Value::Object(Map::from_iter([(last.to_string(), new)])),
+        Value::Object(map) => {
+            map.insert(last.to_string(), new);
+        }
+        _ => return Err(anyhow!("{key}: parent isn't a table")),
+    }
+
+    Ok(())
+}
+
+/// Removes `key`, returning whether it was there.
+fn remove(value: &mut Value, key: &str) -> bool {
+    let (parent, last) = match key.rsplit_once('.') {
+        Some((parent, last)) => (Some(parent), last),
+        None => (None, key),
+    };
+
+    let target = match parent {
+        Some(parent) => parent.split('.').try_fold(value, |value, segment| match value {
+            Value::Object(map) => map.get_mut(segment),
+            Value::Array(items) => items.get_mut(segment.parse::<usize>().ok()?),
+            _ => None,
+        }),
+        None => Some(value),
+    };
+
+    match target {
+        Some(Value::Object(map)) => map.remove(last).is_some(),
+        Some(Value::Array(items)) => match last.parse::<usize>() {
+            Ok(i) if i < items.len() => {
+                items.remove(i);
+                true
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
@@ -1,17 +1,33 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use config::ty::App;
 
+mod configure;
+
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
-    #[arg(short, help = "Program to command")]
-    program: Program,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(short, help = "Program to command", required = true)]
+    program: Option<Program>,
     #[arg(short, help = "Output in JSON format", default_value = "false")]
     json: bool,
-    #[arg(name = "DATA", help = "Data to parse")]
+    #[arg(short, help = "What to search: names, contents or both", default_value = "both")]
+    scope: index::ty::Scope,
+    #[arg(name = "DATA", help = "Data to parse, e.g. a search like `main ext:rs -in:target`")]
     data: Option<String>,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(subcommand, about = "Inspect and edit app configs")]
+    Config(configure::ConfigCommand),
+    #[command(about = "Show how far along the index service is with indexing")]
+    Progress,
+    #[command(about = "Open a file, ranking it higher in later searches")]
+    Open { path: std::path::PathBuf },
+}
+
 #[allow(non_camel_case_types)]
 #[derive(strum::Display, strum::EnumString, Clone, Debug)]
 enum Program {
@@ -21,7 +37,20 @@ enum Program {
 
 fn main() {
     let args = Args::parse();
-    match args.program {
+    match args.command {
+        Some(Command::Config(command)) => return configure::run(command),
+        Some(Command::Progress) => return progress(),
+        Some(Command::Open { path }) => {
+            if let Err(e) = index::launch::open(&path) {
+                eprintln!("Failed to open {}: {e}", path.display());
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => {}
+    }
+
+    match args.program.expect("Program is required without a subcommand") {
         Program::scan => {
             match ipsea::send_command(App::Scan, &(), Some(|_: ()| {})) {
                 Ok(_) => {}
@@ -39,13 +68,19 @@ fn main() {
             println!("Searching for: {}", q);
             ipsea::send_command(
                 App::IndexService,
-                &index::ty::Request { query: q },
-                Some(move |value: index::ty::SearchResult| match args.json {
-                    true => println!("{}", serde_json::to_string(&value).unwrap()),
-                    false => {
+                &index::ty::Request::Search { query: q, scope: args.scope },
+                Some(move |response: index::ty::Response| match response {
+                    index::ty::Response::Error(e) => {
+                        eprintln!("{e}");
+                        std::process::exit(1);
+                    }
+                    index::ty::Response::Progress(_) => {}
+                    index::ty::Response::Result(value) if args.json => println!("{}", serde_json::to_string(&value).unwrap()),
+                    index::ty::Response::Result(value) => {
                         println!(
-                            "{ic}{}\t{}",
+                            "{ic}{}{}\t{}",
                             value.name,
+                            if value.is_dir { "/" } else { "" },
                             value.path,
                             ic = {
                                 if value.is_desktop {
@@ -57,6 +92,9 @@ fn main() {
                                 }
                             }
                         );
+                        if let Some(snippet) = value.snippet {
+                            println!("\t{}: {}", snippet.line, snippet.text);
+                        }
                     }
                 }),
             )
@@ -66,3 +104,20 @@ fn main() {
         }
     }
 }
+
+fn progress() {
+    ipsea::send_command(
+        App::IndexService,
+        &index::ty::Request::Progress,
+        Some(|response: index::ty::Response| {
+            if let index::ty::Response::Progress(p) = response {
+                match p.is_idle() {
+                    true => println!("Idle"),
+                    false => println!("Indexing: {} directories queued, {} in progress", p.queued, p.active),
+                }
+                println!("{} directories indexed, {} entries written since starting", p.scanned, p.written);
+            }
+        }),
+    )
+    .expect("Failed to connect to index");
+}
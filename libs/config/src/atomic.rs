@@ -0,0 +1,54 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    process,
+};
+
+use anyhow::{anyhow, Result};
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Takes an exclusive advisory lock on `<path>.lock`, held until the returned file is dropped.
+/// Keeps two processes (e.g. `finick` and a service) from interleaving writes to `path`.
+pub(crate) fn lock(path: &Path) -> Result<File> {
+    let lock_path = sibling(path, ".lock");
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .map_err(|e| anyhow!("{}: {e}", lock_path.display()))?;
+
+    file.lock().map_err(|e| anyhow!("Failed to lock {}: {e}", lock_path.display()))?;
+    Ok(file)
+}
+
+/// Replaces `path` with `contents` without ever leaving a partially written file behind:
+/// the data goes to a temp file in the same directory, is fsynced, then renamed over `path`.
+pub(crate) fn write(path: &Path, contents: &[u8]) -> Result<()> {
+    let tmp = sibling(path, &format!(".{}.tmp", process::id()));
+
+    let written = (|| {
+        let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    })();
+
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(anyhow!("Failed to write {}: {e}", path.display()));
+    }
+
+    // Persist the rename itself.
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
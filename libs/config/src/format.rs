@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::ty::App;
+
+/// On-disk format of a config file, chosen by its extension.
+#[derive(strum::Display, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `<App>.json`, or a bare `<App>` as written by older releases.
+    Json,
+    Toml,
+    Yaml,
+}
+
+/// Extensions looked for, in order of preference when several files exist.
+const CANDIDATES: &[(&str, Format)] = &[("toml", Format::Toml), ("yaml", Format::Yaml), ("yml", Format::Yaml), ("json", Format::Json)];
+
+impl Format {
+    pub fn from_path(path: &Path) -> Self {
+        let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        CANDIDATES.iter().find(|(e, _)| *e == ext).map_or(Format::Json, |(_, f)| *f)
+    }
+
+    pub fn parse(self, text: &str) -> Result<Value> {
+        match self {
+            Format::Json => serde_json::from_str(text).map_err(|e| anyhow!(e)),
+            Format::Toml => toml::from_str(text).map_err(|e| anyhow!(e)),
+            Format::Yaml => serde_yaml::from_str(text).map_err(|e| anyhow!(e)),
+        }
+    }
+
+    /// Renders `value`, keeping the comments and layout of `previous` where possible (TOML only).
+    pub fn render(self, value: &Value, previous: Option<&str>) -> Result<String> {
+        match self {
+            Format::Json => Ok(serde_json::to_string_pretty(value)?),
+            Format::Yaml => Ok(serde_yaml::to_string(value)?),
+            Format::Toml => {
+                // TOML has no null, so unset options are left out entirely.
+                let fresh = toml::to_string_pretty(&without_nulls(value.clone()))?;
+                match previous.and_then(|p| p.parse::<DocumentMut>().ok()) {
+                    Some(mut doc) => {
+                        update(doc.as_table_mut(), fresh.parse::<DocumentMut>()?.as_table());
+                        Ok(doc.to_string())
+                    }
+                    None => Ok(fresh),
+                }
+            }
+        }
+    }
+}
+
+/// Finds the config file for `app` in `dir`, if there is one.
+pub(crate) fn locate(dir: &Path, app: &App) -> Option<PathBuf> {
+    CANDIDATES
+        .iter()
+        .map(|(ext, _)| dir.join(format!("{app}.{ext}")))
+        .chain([dir.join(app.to_string())])
+        .find(|p| p.is_file())
+}
+
+/// Whether `path` is one of the names [`locate`] would pick for `app`.
+pub(crate) fn is_config_file(path: &Path, app: &App) -> bool {
+    let name = app.to_string();
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => {
+            *stem == *name && CANDIDATES.iter().any(|(e, _)| *ext == **e)
+        }
+        (Some(stem), None) => *stem == *name,
+        _ => false,
+    }
+}
+
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            Value::Object(map.into_iter().filter(|(_, v)| !v.is_null()).map(|(k, v)| (k, without_nulls(v))).collect())
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(without_nulls).collect()),
+        value => value,
+    }
+}
+
+/// Makes `existing` hold the same data as `fresh`, reusing existing entries
+/// (and so their comments and formatting) wherever the key is still present.
+fn update(existing: &mut Table, fresh: &Table) {
+    let stale = existing.iter().map(|(k, _)| k.to_string()).filter(|k| !fresh.contains_key(k)).collect::<Vec<_>>();
+    for key in stale {
+        existing.remove(&key);
+    }
+
+    for (key, item) in fresh.iter() {
+        match (existing.get_mut(key), item) {
+            (Some(Item::Table(old)), Item::Table(new)) => update(old, new),
+            (Some(Item::Value(old)), Item::Value(new)) => {
+                let decor = old.decor().clone();
+                *old = new.clone();
+                *old.decor_mut() = decor;
+            }
+            _ => {
+                existing.insert(key, item.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::{collections::BTreeMap, env};
+
+use serde_json::{Map, Value};
+
+use crate::ty::{App, Layer};
+
+/// Overlays `value` onto `base` field-by-field, recording which layer
+/// each leaf came from under its dotted key.
+pub(crate) fn merge(base: &mut Value, value: Value, layer: Layer, prefix: &str, sources: &mut BTreeMap<String, Layer>) {
+    match (base, value) {
+        (Value::Object(base), Value::Object(value)) => {
+            for (key, value) in value {
+                let path = join(prefix, &key);
+                merge(base.entry(key).or_insert(Value::Null), value, layer, &path, sources);
+            }
+        }
+        (base, value) => {
+            sources.retain(|k, _| !is_within(k, prefix));
+            mark(&value, layer, prefix, sources);
+            *base = value;
+        }
+    }
+}
+
+/// Records `layer` as the source of every leaf in `value`.
+pub(crate) fn mark(value: &Value, layer: Layer, prefix: &str, sources: &mut BTreeMap<String, Layer>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            map.iter().for_each(|(key, value)| mark(value, layer, &join(prefix, key), sources))
+        }
+        _ => {
+            sources.insert(prefix.to_string(), layer);
+        }
+    }
+}
+
+/// Collects `FINICK_<APP>_<KEY>` variables into an object shaped like `shape`.
+/// Nested keys are separated by a double underscore (`FINICK_APP_OUTER__INNER`),
+/// and are matched case-insensitively against the keys already present.
+/// Values are parsed as JSON, falling back to a plain string.
+pub(crate) fn environment(app: &App, shape: &Value) -> Value {
+    let prefix = format!("FINICK_{}_", app.to_string().to_uppercase());
+    let mut overrides = Value::Object(Map::new());
+
+    for (name, raw) in env::vars() {
+        let Some(key) = name.strip_prefix(&prefix) else { continue };
+        let value = serde_json::from_str(&raw).unwrap_or(Value::String(raw));
+
+        let mut shape = Some(shape);
+        let mut target = &mut overrides;
+        let segments = key.split("__").collect::<Vec<_>>();
+        for (i, segment) in segments.iter().enumerate() {
+            let key = shape
+                .and_then(Value::as_object)
+                .and_then(|m| m.keys().find(|k| k.eq_ignore_ascii_case(segment)))
+                .cloned()
+                .unwrap_or_else(|| segment.to_lowercase());
+            shape = shape.and_then(|s| s.get(&key));
+
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+
+            let entry = target.as_object_mut().unwrap().entry(key).or_insert(Value::Null);
+            if i == segments.len() - 1 {
+                *entry = value.clone();
+            }
+            target = entry;
+        }
+    }
+
+    overrides
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{prefix}.{key}")
+    }
+}
+
+fn is_within(key: &str, prefix: &str) -> bool {
+    prefix.is_empty() || key == prefix || key.starts_with(&format!("{prefix}."))
+}
@@ -1,50 +1,211 @@
 use std::{
-    env::var,
-    fs::{self, File, OpenOptions},
+    collections::BTreeMap,
+    fs,
     path::{Path, PathBuf},
 };
 
 use anyhow::{anyhow, Result};
+use log::{info, warn};
 use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
 use ty::*;
 
+mod atomic;
+pub mod format;
+mod layer;
+pub mod migrate;
+pub mod paths;
 pub mod ty;
+pub mod validate;
+mod watch;
 
-pub fn finick_root() -> PathBuf {
-    let path = Path::new(&var("HOME").expect("No home dir exists"))
-        .join(".config")
-        .join(".finick");
+pub use format::Format;
+pub use paths::{cache_dir, config_dir, data_dir};
+pub use validate::{ConfigError, ConfigErrors, FieldError, Validate};
+pub use watch::{watch, ConfigWatch};
 
-    if !path.exists() {
-        fs::DirBuilder::new()
-            .recursive(true)
-            .create(path.clone())
-            .expect("Failed to create finick dir");
-    }
+/// Directory holding system-wide defaults, shipped by admins or packagers.
+pub const SYSTEM_ROOT: &str = "/etc/finick";
+
+fn read_layer(path: &Path) -> Result<Value> {
+    fs::read_to_string(path)
+        .map_err(|e| anyhow!(e))
+        .and_then(|text| Format::from_path(path).parse(&text))
+        .map_err(|e| {
+            let location = validate::display_path(path);
+            anyhow!(ConfigErrors(vec![ConfigError { location, field: String::new(), message: e.to_string() }]))
+        })
+}
+
+/// Path of the user's config file for `app`, which may not exist yet.
+/// Any of `<App>.toml`, `<App>.yaml`, `<App>.yml`, `<App>.json` or a bare
+/// `<App>` (JSON) is picked up, with new files written as the latter.
+pub fn config_path(app: &App) -> Result<PathBuf> {
+    let dir = config_dir()?;
+    Ok(format::locate(&dir, app).unwrap_or_else(|| dir.join(app.to_string())))
+}
 
-    path
+/// Resolves the config for `app` from, in increasing priority:
+/// `T::default()`, `/etc/finick/<App>`, the user's `<App>` file and
+/// `FINICK_<APP>_<KEY>` environment variables, merged field-by-field.
+/// Files may be JSON, TOML or YAML, see [`config_path`].
+/// Missing files are skipped rather than created, so defaults from lower
+/// layers aren't shadowed by a freshly written user file.
+///
+/// Files from an older schema version are run through the migrations registered
+/// with [`migrate::register`]; the user file is upgraded in place, keeping a
+/// `<App>.v<version>.bak` backup. Unknown fields are logged and ignored.
+///
+/// Malformed files, mistyped fields and values rejected by [`Validate`] fail
+/// with [`ConfigErrors`], each naming the file and field at fault.
+pub fn get_config_layered<T: DeserializeOwned + Serialize + Default + Validate>(app: App) -> Result<Layered<T>> {
+    resolve(app, None)
 }
 
-pub fn get_config<T: DeserializeOwned + Serialize + Default>(app: App) -> Result<T> {
-    let path = finick_root().join(app.to_string());
-    if !path.exists() {
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(path.clone())?;
-        serde_json::to_writer_pretty(file, &T::default())?;
-        Ok(T::default())
-    } else {
-        serde_json::from_reader(File::open(path)?).map_err(|e| anyhow!(e))
+/// Resolves like [`get_config_layered`], optionally with `user` standing in for the user file.
+fn resolve<T: DeserializeOwned + Serialize + Default + Validate>(app: App, user: Option<Value>) -> Result<Layered<T>> {
+    let mut sources = BTreeMap::new();
+    let mut value = serde_json::to_value(T::default())?;
+    layer::mark(&value, Layer::Default, "", &mut sources);
+
+    let user_path = match user {
+        Some(_) => Some(config_path(&app)?),
+        None => format::locate(&config_dir()?, &app),
+    };
+    let files = [(Layer::System, format::locate(Path::new(SYSTEM_ROOT), &app)), (Layer::User, user_path)];
+
+    let files = files.into_iter().filter_map(|(s, p)| Some((s, p?))).collect::<BTreeMap<_, _>>();
+    for (&source, path) in &files {
+        let mut file = match (source, &user) {
+            (Layer::User, Some(user)) => user.clone(),
+            _ => read_layer(path)?,
+        };
+
+        if let Some(from) = migrate::upgrade(&app, &mut file)? {
+            info!("Migrated {} from version {from}", path.display());
+            if source == Layer::User && user.is_none() {
+                persist_upgrade(path, from, &file)?;
+            }
+        }
+
+        migrate::strip(&mut file);
+        layer::merge(&mut value, file, source, "", &mut sources);
+    }
+
+    let env = layer::environment(&app, &value);
+    layer::merge(&mut value, env, Layer::Environment, "", &mut sources);
+
+    let typed: T = serde_path_to_error::deserialize(value.clone()).map_err(|e| {
+        let field = e.path().to_string().trim_start_matches('.').to_string();
+        let location = validate::locate(&field, &sources, &files);
+        ConfigErrors(vec![ConfigError { location, field, message: e.into_inner().to_string() }])
+    })?;
+
+    let invalid = typed.validate();
+    if !invalid.is_empty() {
+        return Err(anyhow!(ConfigErrors(
+            invalid
+                .into_iter()
+                .map(|e| ConfigError { location: validate::locate(&e.field, &sources, &files), field: e.field, message: e.message })
+                .collect()
+        )));
+    }
+
+    for field in migrate::unknown_fields(&value, &serde_json::to_value(&typed)?, "") {
+        warn!("Ignoring unknown field `{field}` in {app} config");
     }
+
+    Ok(Layered { value: typed, sources })
+}
+
+/// Backs up the pre-migration file and writes the upgraded one over it.
+fn persist_upgrade(path: &Path, from: u32, upgraded: &Value) -> Result<()> {
+    let _lock = atomic::lock(path)?;
+
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push(format!(".v{from}.bak"));
+    fs::copy(path, &backup)?;
+
+    let previous = fs::read_to_string(path).ok();
+    let contents = Format::from_path(path).render(upgraded, previous.as_deref())?;
+    atomic::write(path, contents.as_bytes())
 }
 
+pub fn get_config<T: DeserializeOwned + Serialize + Default + Validate>(app: App) -> Result<T> {
+    get_config_layered(app).map(|c| c.value)
+}
+
+/// Atomically replaces the user's config for `app`, holding an advisory lock
+/// so concurrent writers from other processes can't interleave.
+/// Existing files keep their format, and TOML files keep their comments.
 pub fn write_config<T: Serialize>(app: App, value: T) -> Result<()> {
-    let path = finick_root().join(app.to_string());
-    let file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .open(path.clone())?;
-    serde_json::to_writer_pretty(file, &value)?;
-    Ok(())
+    let path = config_path(&app)?;
+    let mut value = serde_json::to_value(value)?;
+    migrate::stamp(&app, &mut value);
+
+    let _lock = atomic::lock(&path)?;
+    render_over(&path, &value)
+}
+
+/// Writes `value` over `path` in its format. Callers must hold the lock.
+fn render_over(path: &Path, value: &Value) -> Result<()> {
+    let previous = fs::read_to_string(path).ok();
+    let contents = Format::from_path(path).render(value, previous.as_deref())?;
+    atomic::write(path, contents.as_bytes())
+}
+
+/// The user's own config file for `app`, without defaults or other layers.
+/// Older schema versions are upgraded in memory. Empty if there's no file yet.
+pub fn read_user_config(app: &App) -> Result<Value> {
+    let Some(path) = format::locate(&config_dir()?, app) else { return Ok(Value::Object(Default::default())) };
+
+    let mut value = read_layer(&path)?;
+    migrate::upgrade(app, &mut value)?;
+    migrate::strip(&mut value);
+    Ok(value)
+}
+
+/// Checks that `user` would resolve to a valid `T` if it were the user's config file.
+pub fn check_user_config<T: DeserializeOwned + Serialize + Default + Validate>(app: App, user: &Value) -> Result<()> {
+    resolve::<T>(app, Some(user.clone())).map(|_| ())
+}
+
+/// Replaces the user's config file with `user`, if it passes [`check_user_config`].
+pub fn write_user_config<T: DeserializeOwned + Serialize + Default + Validate>(app: App, user: Value) -> Result<()> {
+    check_user_config::<T>(app.clone(), &user)?;
+    write_config(app, user)
+}
+
+/// Lets `f` change the user's config file for `app` and writes the result back if it passes
+/// [`check_user_config`]. The lock is held from the read to the write, so a concurrent
+/// writer can't land in between and be overwritten.
+pub fn update_user_config<T: DeserializeOwned + Serialize + Default + Validate>(
+    app: App,
+    f: impl FnOnce(&mut Value) -> Result<()>,
+) -> Result<()> {
+    let path = config_path(&app)?;
+    let _lock = atomic::lock(&path)?;
+
+    let mut user = read_user_config(&app)?;
+    f(&mut user)?;
+    check_user_config::<T>(app.clone(), &user)?;
+    migrate::stamp(&app, &mut user);
+    render_over(&path, &user)
+}
+
+/// Replaces the user's config file with `text` verbatim (keeping every comment),
+/// if it parses in the file's format and passes [`check_user_config`].
+pub fn replace_user_config<T: DeserializeOwned + Serialize + Default + Validate>(app: App, text: &str) -> Result<()> {
+    let path = config_path(&app)?;
+    let mut user = Format::from_path(&path).parse(text).map_err(|e| {
+        let location = validate::display_path(&path);
+        anyhow!(ConfigErrors(vec![ConfigError { location, field: String::new(), message: e.to_string() }]))
+    })?;
+
+    migrate::upgrade(&app, &mut user)?;
+    migrate::strip(&mut user);
+    check_user_config::<T>(app, &user)?;
+
+    let _lock = atomic::lock(&path)?;
+    atomic::write(&path, text.as_bytes())
 }
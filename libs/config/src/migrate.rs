@@ -0,0 +1,87 @@
+//! Schema versions and upgrade chains for config files.
+//!
+//! Every file carries its schema version under [`VERSION_KEY`] (missing means `0`).
+//! Apps register a chain of migrations, where the `n`th one upgrades a file from
+//! version `n` to `n + 1`, so the current version is the length of the chain.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, RwLock},
+};
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde_json::Value;
+
+use crate::ty::App;
+
+pub const VERSION_KEY: &str = "config_version";
+
+/// Upgrades a config one version, in place.
+pub type Migration = fn(&mut Value) -> Result<()>;
+
+static MIGRATIONS: LazyLock<RwLock<HashMap<App, Vec<Migration>>>> = LazyLock::new(Default::default);
+
+/// Registers the migration chain for `app`, replacing any previous one.
+/// Should be called before the app first reads its config.
+pub fn register(app: App, chain: Vec<Migration>) { MIGRATIONS.write().unwrap().insert(app, chain); }
+
+/// The schema version new files for `app` are written with.
+pub fn current_version(app: &App) -> u32 { MIGRATIONS.read().unwrap().get(app).map_or(0, |c| c.len() as u32) }
+
+pub(crate) fn version_of(value: &Value) -> u32 {
+    value.get(VERSION_KEY).and_then(Value::as_u64).unwrap_or(0) as u32
+}
+
+/// Runs every migration needed to bring `value` up to the current version,
+/// returning the version it started at if anything changed.
+pub(crate) fn upgrade(app: &App, value: &mut Value) -> Result<Option<u32>> {
+    let from = version_of(value);
+    let chain = MIGRATIONS.read().unwrap().get(app).cloned().unwrap_or_default();
+
+    if from as usize > chain.len() {
+        warn!("{app} config is version {from}, newer than the supported {}", chain.len());
+        return Ok(None);
+    }
+
+    if from as usize == chain.len() {
+        return Ok(None);
+    }
+
+    for (version, migration) in chain.iter().enumerate().skip(from as usize) {
+        migration(value).map_err(|e| anyhow!("Failed to migrate {app} config from version {version}: {e}"))?;
+    }
+
+    stamp(app, value);
+    Ok(Some(from))
+}
+
+/// Sets the version of `value` to the current one, if `app` has any migrations.
+pub(crate) fn stamp(app: &App, value: &mut Value) {
+    let version = current_version(app);
+    if let (Value::Object(map), true) = (value, version > 0) {
+        map.insert(VERSION_KEY.to_string(), version.into());
+    }
+}
+
+pub(crate) fn strip(value: &mut Value) {
+    if let Value::Object(map) = value {
+        map.remove(VERSION_KEY);
+    }
+}
+
+/// Dotted paths present in `given` which didn't survive a round trip into `known`.
+pub(crate) fn unknown_fields(given: &Value, known: &Value, prefix: &str) -> Vec<String> {
+    let (Value::Object(given), Value::Object(known)) = (given, known) else { return vec![] };
+
+    given
+        .iter()
+        .flat_map(|(key, value)| {
+            let path = if prefix.is_empty() { key.clone() } else { format!("{prefix}.{key}") };
+            match known.get(key) {
+                Some(known) => unknown_fields(value, known, &path),
+                None => vec![path],
+            }
+        })
+        .collect()
+}
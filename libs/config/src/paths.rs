@@ -0,0 +1,107 @@
+//! Locations of finick's files, following the XDG base directory spec.
+
+use std::{
+    env::var_os,
+    fs,
+    path::{Path, PathBuf},
+    sync::Once,
+};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+
+const NAME: &str = "finick";
+
+/// Files in the legacy root that belong in the data dir rather than the config dir.
+const LEGACY_DATA: &[&str] = &["index.db", "index.db-wal", "index.db-shm"];
+/// Directories in the legacy root that belong in the cache dir.
+const LEGACY_CACHE: &[&str] = &["icons"];
+
+static MIGRATE: Once = Once::new();
+
+fn home() -> Result<PathBuf> {
+    var_os("HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .ok_or_else(|| anyhow!("Neither $HOME nor the relevant $XDG_*_HOME is set"))
+}
+
+/// Resolves `$<var>/finick`, or `~/<fallback>/finick` when the variable
+/// is unset or relative (which the spec says to ignore).
+fn xdg(var: &str, fallback: &str) -> Result<PathBuf> {
+    let base = match var_os(var).map(PathBuf::from).filter(|p| p.is_absolute()) {
+        Some(base) => base,
+        None => home()?.join(fallback),
+    };
+
+    Ok(base.join(NAME))
+}
+
+fn ensure(path: PathBuf) -> Result<PathBuf> {
+    MIGRATE.call_once(migrate_legacy);
+    fs::create_dir_all(&path).map_err(|e| anyhow!("Failed to create {}: {e}", path.display()))?;
+    Ok(path)
+}
+
+/// User config files, `$XDG_CONFIG_HOME/finick`.
+pub fn config_dir() -> Result<PathBuf> { ensure(xdg("XDG_CONFIG_HOME", ".config")?) }
+
+/// Persistent data such as the index database, `$XDG_DATA_HOME/finick`.
+pub fn data_dir() -> Result<PathBuf> { ensure(xdg("XDG_DATA_HOME", ".local/share")?) }
+
+/// Regenerable files such as resized icons, `$XDG_CACHE_HOME/finick`.
+pub fn cache_dir() -> Result<PathBuf> { ensure(xdg("XDG_CACHE_HOME", ".cache")?) }
+
+/// The old root everything was kept in, `~/.config/.finick`, emptied into the XDG directories
+/// the first time one is used. Paths into it stored elsewhere, like the index's cached icons,
+/// are for their owners to update.
+pub fn legacy_dir() -> Result<PathBuf> { Ok(home()?.join(".config").join(".finick")) }
+
+/// Moves everything out of [`legacy_dir`] into the XDG directories,
+/// leaving anything that already exists at the destination.
+fn migrate_legacy() {
+    let Ok(legacy) = legacy_dir() else { return };
+    let Ok(entries) = fs::read_dir(&legacy) else { return };
+
+    info!("Migrating files from {}", legacy.display());
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let dir = if LEGACY_DATA.contains(&name.as_str()) {
+            xdg("XDG_DATA_HOME", ".local/share")
+        } else if LEGACY_CACHE.contains(&name.as_str()) {
+            xdg("XDG_CACHE_HOME", ".cache")
+        } else {
+            xdg("XDG_CONFIG_HOME", ".config")
+        };
+
+        let dir = match dir {
+            Ok(dir) => dir,
+            Err(e) => {
+                warn!("Failed to migrate {}: {e}", entry.path().display());
+                continue;
+            }
+        };
+        if let Err(e) = relocate(&entry.path(), &dir.join(&name)) {
+            warn!("Failed to migrate {}: {e}", entry.path().display());
+        }
+    }
+
+    // Only succeeds once everything has been moved.
+    if fs::remove_dir(&legacy).is_ok() {
+        info!("Removed legacy directory {}", legacy.display());
+    }
+}
+
+fn relocate(from: &Path, to: &Path) -> Result<()> {
+    if to.exists() {
+        return Err(anyhow!("{} already exists", to.display()));
+    }
+
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::rename(from, to)?;
+    info!("Moved {} to {}", from.display(), to.display());
+    Ok(())
+}
@@ -1,9 +1,42 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
-#[derive(strum::Display, strum::EnumString, Serialize, Deserialize, Debug, Clone)]
+#[derive(strum::Display, strum::EnumString, Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum App {
     Scan,
     Files,
     IndexService,
     Other(String),
-}
\ No newline at end of file
+}
+
+/// Where a config value was resolved from, lowest priority first.
+#[derive(strum::Display, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    /// The type's `Default` implementation.
+    Default,
+    /// The system-wide file in `/etc/finick`.
+    System,
+    /// The user's own config file.
+    User,
+    /// A `FINICK_<APP>_<KEY>` environment variable.
+    Environment,
+}
+
+/// A resolved config, along with the layer each value came from.
+#[derive(Debug, Clone)]
+pub struct Layered<T> {
+    pub value: T,
+    /// Source layer of every leaf value, keyed by dotted path (e.g. `folders`, `limits.results`).
+    pub sources: BTreeMap<String, Layer>,
+}
+
+impl<T> Layered<T> {
+    /// Layer the value at `key` (or any value beneath it) was last set by.
+    pub fn source(&self, key: &str) -> Option<Layer> {
+        self.sources.get(key).copied().or_else(|| {
+            let prefix = format!("{key}.");
+            self.sources.iter().filter(|(k, _)| k.starts_with(&prefix)).map(|(_, l)| *l).max()
+        })
+    }
+}
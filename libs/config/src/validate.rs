@@ -0,0 +1,102 @@
+use std::{
+    collections::BTreeMap,
+    env::var_os,
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::ty::Layer;
+
+/// A problem with a single field, e.g. `folders[2]`, `path does not exist`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    /// Path to the field, in the same `outer.inner[index]` form serde errors use.
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl ToString, message: impl ToString) -> Self {
+        Self { field: field.to_string(), message: message.to_string() }
+    }
+}
+
+/// Checks a config for problems serde can't catch, such as missing paths.
+/// Invalid configs are rejected by `get_config` and skipped by `watch`.
+pub trait Validate {
+    fn validate(&self) -> Vec<FieldError> { Vec::new() }
+}
+
+/// Untyped configs are only checked for syntax.
+impl Validate for Value {}
+
+/// A problem with a config, located down to the file and field it came from.
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    /// Where the offending value was set, e.g. a file or `environment`.
+    pub location: String,
+    /// Path to the field, empty if the error isn't about a single field.
+    pub field: String,
+    pub message: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.location)?;
+        if !self.field.is_empty() {
+            write!(f, "{}: ", self.field)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Every problem found in a config, one per line when displayed.
+#[derive(Debug, Clone)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines = self.0.iter().map(ToString::to_string).collect::<Vec<_>>();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// Shortens paths under `$HOME` to `~/...`.
+pub(crate) fn display_path(path: &Path) -> String {
+    match var_os("HOME").map(PathBuf::from).and_then(|h| path.strip_prefix(h).ok().map(Path::to_path_buf)) {
+        Some(rest) => format!("~/{}", rest.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// Works out which file (or the environment) set `field`, going by the layer
+/// recorded for it, or failing that for the nearest parent that has one.
+pub(crate) fn locate(field: &str, sources: &BTreeMap<String, Layer>, files: &BTreeMap<Layer, PathBuf>) -> String {
+    let mut key = field.split('[').next().unwrap_or_default().to_string();
+
+    let layer = loop {
+        let prefix = format!("{key}.");
+        let layer = sources
+            .get(&key)
+            .copied()
+            .or_else(|| sources.iter().filter(|(k, _)| k.starts_with(&prefix)).map(|(_, l)| *l).max());
+
+        match (layer, key.rsplit_once('.')) {
+            (Some(layer), _) => break Some(layer),
+            (None, Some((parent, _))) => key = parent.to_string(),
+            (None, None) => break None,
+        }
+    };
+
+    match layer {
+        Some(Layer::Environment) => "environment".to_string(),
+        Some(layer) => files.get(&layer).map_or_else(|| layer.to_string(), |p| display_path(p)),
+        None => "config".to_string(),
+    }
+}
@@ -0,0 +1,74 @@
+use std::{
+    path::Path,
+    sync::mpsc::{self, Receiver},
+};
+
+use anyhow::Result;
+use log::{info, warn};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{config_dir, format, get_config, ty::App, Validate, SYSTEM_ROOT};
+
+/// Stream of reloaded configs for an app, see [`watch`].
+/// Watching stops once this is dropped.
+pub struct ConfigWatch<T> {
+    _watcher: RecommendedWatcher,
+    rx: Receiver<T>,
+}
+
+impl<T> ConfigWatch<T> {
+    /// Blocks until the config changes, returning `None` if the watcher died.
+    pub fn recv(&self) -> Option<T> { self.rx.recv().ok() }
+
+    /// Returns the latest change since last called, if any.
+    pub fn try_recv(&self) -> Option<T> { self.rx.try_iter().last() }
+}
+
+impl<T> Iterator for ConfigWatch<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> { self.recv() }
+}
+
+/// Watches every file `get_config` reads for `app`, re-resolving the config
+/// whenever one changes. New values are only delivered if they parse, validate
+/// and differ from the last one, so an invalid edit leaves the previous value in place.
+pub fn watch<T>(app: App) -> Result<ConfigWatch<T>>
+where
+    T: DeserializeOwned + Serialize + Default + Validate + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    let name = app.to_string();
+    let mut last = serde_json::to_value(get_config::<T>(app.clone()).unwrap_or_default())?;
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => return warn!("Config watcher error: {e}"),
+        };
+
+        if event.kind.is_access() || !event.paths.iter().any(|p| format::is_config_file(p, &app)) {
+            return;
+        }
+
+        match get_config::<T>(app.clone()) {
+            Ok(value) => {
+                let Ok(json) = serde_json::to_value(&value) else { return };
+                if json != last {
+                    info!("Reloaded {name} config");
+                    last = json;
+                    let _ = tx.send(value);
+                }
+            }
+            Err(e) => warn!("Ignoring invalid {name} config, keeping the previous one: {e}"),
+        }
+    })?;
+
+    watcher.watch(&config_dir()?, RecursiveMode::NonRecursive)?;
+    if Path::new(SYSTEM_ROOT).is_dir() {
+        watcher.watch(Path::new(SYSTEM_ROOT), RecursiveMode::NonRecursive)?;
+    }
+
+    Ok(ConfigWatch { _watcher: watcher, rx })
+}
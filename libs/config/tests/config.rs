@@ -0,0 +1,152 @@
+//! Resolving and writing configs, see `src/lib.rs`.
+//!
+//! Tests run in parallel within the one process, so each uses an app of its own.
+
+use std::{env, fs, path::PathBuf, sync::LazyLock, thread};
+
+use config::{
+    ty::{App, Layer},
+    ConfigErrors, FieldError, Validate,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+#[serde(default)]
+struct Sample {
+    name: String,
+    count: u32,
+    nested: Nested,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+#[serde(default)]
+struct Nested {
+    flag: bool,
+    items: Vec<String>,
+}
+
+impl Validate for Sample {
+    fn validate(&self) -> Vec<FieldError> {
+        match self.count {
+            100.. => vec![FieldError::new("count", "must be below 100")],
+            _ => vec![],
+        }
+    }
+}
+
+/// The config dir, with `$HOME` and `$XDG_CONFIG_HOME` pointing into it.
+static DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+    let root = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("config");
+    let _ = fs::remove_dir_all(&root);
+
+    env::set_var("HOME", &root);
+    env::set_var("XDG_CONFIG_HOME", root.join("config"));
+    config::config_dir().unwrap()
+});
+
+fn write(app: &App, ext: &str, contents: &str) -> PathBuf {
+    let path = DIR.join(format!("{app}.{ext}"));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+/// Every error resolving `app`, as displayed.
+fn errors(app: App) -> Vec<String> {
+    let error = config::get_config::<Sample>(app).unwrap_err();
+    let errors = error.downcast::<ConfigErrors>().unwrap_or_else(|e| panic!("Not a config error: {e}"));
+    errors.0.iter().map(ToString::to_string).collect()
+}
+
+#[test]
+fn layers_files_and_environment_over_defaults() {
+    let app = App::Scan;
+    write(&app, "toml", "name = \"user\"\n\n[nested]\nitems = [\"a\", \"b\"]\n");
+    env::set_var("FINICK_SCAN_NAME", "environment");
+
+    let layered = config::get_config_layered::<Sample>(app).unwrap();
+    assert_eq!(layered.value, Sample {
+        name: "environment".into(),
+        count: 0,
+        nested: Nested { flag: false, items: vec!["a".into(), "b".into()] },
+    });
+
+    assert_eq!(layered.source("name"), Some(Layer::Environment));
+    assert_eq!(layered.source("count"), Some(Layer::Default));
+    assert_eq!(layered.source("nested.flag"), Some(Layer::Default));
+    assert_eq!(layered.source("nested.items"), Some(Layer::User));
+
+    // A table's source is the highest of what's in it
+    assert_eq!(layered.source("nested"), Some(Layer::User));
+    assert_eq!(layered.source("missing"), None);
+}
+
+#[test]
+fn overrides_nested_keys_from_the_environment() {
+    let app = App::Files;
+    write(&app, "json", r#"{ "count": 1 }"#);
+
+    env::set_var("FINICK_FILES_COUNT", "7");
+    env::set_var("FINICK_FILES_NESTED__FLAG", "true");
+    env::set_var("FINICK_FILES_NESTED__ITEMS", r#"["x"]"#);
+    let value = config::get_config::<Sample>(app.clone()).unwrap();
+    assert_eq!((value.count, value.nested.flag, value.nested.items), (7, true, vec!["x".to_string()]));
+
+    // Values which aren't JSON are strings, so fail where a number is expected
+    env::set_var("FINICK_FILES_COUNT", "seven");
+    let not_a_number = errors(app.clone());
+    assert_eq!(not_a_number.len(), 1);
+    assert!(not_a_number[0].starts_with("environment: count: invalid type: string \"seven\""), "{not_a_number:?}");
+
+    // And are validated like any other
+    env::set_var("FINICK_FILES_COUNT", "700");
+    assert_eq!(errors(app), ["environment: count: must be below 100"]);
+}
+
+#[test]
+fn locates_invalid_values_in_the_file_that_set_them() {
+    let app = App::IndexService;
+    write(&app, "yaml", "count: 500\n");
+    assert_eq!(errors(app.clone()), ["~/config/finick/IndexService.yaml: count: must be below 100"]);
+
+    write(&app, "yaml", "nested:\n  items: 3\n");
+    let wrong_type = errors(app.clone());
+    assert!(wrong_type[0].starts_with("~/config/finick/IndexService.yaml: nested.items: invalid type"), "{wrong_type:?}");
+
+    // Or just the file, for ones that don't parse
+    write(&app, "yaml", "nested: [\n");
+    let unparsed = errors(app);
+    assert!(unparsed[0].starts_with("~/config/finick/IndexService.yaml: "), "{unparsed:?}");
+}
+
+#[test]
+fn keeps_toml_comments_and_every_concurrent_update() {
+    let app = App::Other("Other".into());
+    let path = write(&app, "toml", "# Top\nname = \"kept\" # inline\ncount = 0\n\n# Nested\n[nested]\nflag = true\n");
+
+    let threads = (0..8).map(|_| {
+        let app = app.clone();
+        thread::spawn(move || {
+            config::update_user_config::<Sample>(app, |user| {
+                user["count"] = json!(user["count"].as_u64().unwrap() + 1);
+                Ok(())
+            })
+        })
+    });
+    threads.collect::<Vec<_>>().into_iter().for_each(|t| t.join().unwrap().unwrap());
+
+    let text = fs::read_to_string(&path).unwrap();
+    assert_eq!(text, "# Top\nname = \"kept\" # inline\ncount = 8\n\n# Nested\n[nested]\nflag = true\n");
+
+    // Invalid updates aren't written
+    let invalid = config::update_user_config::<Sample>(app, |user| {
+        user["count"] = json!(500);
+        Ok(())
+    });
+    assert!(invalid.is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), text);
+
+    // Nor are temp files left behind
+    let names = fs::read_dir(&*DIR).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().to_string());
+    assert_eq!(names.filter(|name| name.ends_with(".tmp")).count(), 0);
+}
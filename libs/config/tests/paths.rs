@@ -0,0 +1,25 @@
+//! Finding the XDG directories, see `src/paths.rs`.
+
+use std::{env, path::PathBuf};
+
+#[test]
+fn falls_back_to_home_without_an_absolute_xdg_variable() {
+    let root = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("paths");
+    env::set_var("HOME", &root);
+
+    env::remove_var("XDG_CONFIG_HOME");
+    env::remove_var("XDG_DATA_HOME");
+    env::set_var("XDG_CACHE_HOME", "relative/cache");
+    assert_eq!(config::config_dir().unwrap(), root.join(".config/finick"));
+    assert_eq!(config::data_dir().unwrap(), root.join(".local/share/finick"));
+    assert_eq!(config::cache_dir().unwrap(), root.join(".cache/finick"));
+    assert!(root.join(".config/finick").is_dir());
+
+    env::set_var("XDG_CONFIG_HOME", root.join("elsewhere"));
+    assert_eq!(config::config_dir().unwrap(), root.join("elsewhere/finick"));
+
+    // Without a home, only absolute XDG variables will do
+    env::remove_var("HOME");
+    assert!(config::data_dir().is_err());
+    assert_eq!(config::config_dir().unwrap(), root.join("elsewhere/finick"));
+}
@@ -0,0 +1,77 @@
+//! Reloading configs as they're edited, see `src/watch.rs`.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use config::{ty::App, ConfigWatch, FieldError, Validate};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+#[serde(default)]
+struct Sample {
+    count: u32,
+}
+
+impl Validate for Sample {
+    fn validate(&self) -> Vec<FieldError> {
+        match self.count {
+            100.. => vec![FieldError::new("count", "must be below 100")],
+            _ => vec![],
+        }
+    }
+}
+
+/// Replaces `path` in one go, as a half-written file could be a valid config of its own.
+fn write(path: &Path, contents: &str) {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents).unwrap();
+    fs::rename(tmp, path).unwrap();
+}
+
+/// The latest value `watch` delivers within a second, if any.
+fn next(watch: &ConfigWatch<Sample>) -> Option<Sample> {
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while Instant::now() < deadline {
+        if let Some(value) = watch.try_recv() {
+            return Some(value);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    None
+}
+
+#[test]
+fn keeps_the_last_good_value_through_invalid_edits() {
+    let root = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("watch");
+    let _ = fs::remove_dir_all(&root);
+    env::set_var("HOME", &root);
+    env::set_var("XDG_CONFIG_HOME", &root);
+
+    let path = config::config_dir().unwrap().join("Scan.toml");
+    write(&path, "count = 1\n");
+    let watch = config::watch::<Sample>(App::Scan).unwrap();
+
+    write(&path, "count = 2\n");
+    assert_eq!(next(&watch), Some(Sample { count: 2 }));
+
+    // Neither unparsable nor invalid edits are delivered
+    write(&path, "count = [\n");
+    assert_eq!(next(&watch), None);
+    write(&path, "count = 500\n");
+    assert_eq!(next(&watch), None);
+
+    // Nor ones which resolve to what was last delivered
+    write(&path, "# Unchanged\ncount = 2\n");
+    assert_eq!(next(&watch), None);
+
+    // Nor other apps' files
+    write(&path.with_file_name("Files.toml"), "count = 3\n");
+    assert_eq!(next(&watch), None);
+
+    write(&path, "count = 4\n");
+    assert_eq!(next(&watch), Some(Sample { count: 4 }));
+}
@@ -1,9 +1,3 @@
-#[cfg(feature = "bincode")]
-use bincode;
-
-#[cfg(not(feature = "bincode"))]
-use serde_json;
-
 use log::{error, info, trace};
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
@@ -48,103 +42,169 @@ pub enum StreamResponse<T> {
     EndOfStream,
 }
 
-pub(crate) fn process<Res, Req>(mut stream: UnixStream) -> Option<(Req, Sender<Res>)>
-where
-    Req: for<'de> Deserialize<'de> + Send + 'static + std::fmt::Debug,
-    Res: Serialize + Send + 'static + std::fmt::Debug,
-{
-    let mut len_buf = [0u8; 4];
-    if stream.read_exact(&mut len_buf).is_err() {
-        error!("Failed to read request length");
-        return None;
+#[derive(Serialize, Deserialize, Debug)]
+pub enum StreamRequest<T> {
+    Data(T),
+    EndOfStream,
+}
+
+fn socket_path(socket: impl Display) -> PathBuf {
+    let socket = socket.to_string();
+    if socket.starts_with("/") {
+        PathBuf::from(socket)
+    } else {
+        PathBuf::from(format!("/tmp/{}.sock", socket))
     }
+}
 
-    trace!("Length bytes: {:?}", len_buf);
-    let req_len = u32::from_le_bytes(len_buf) as usize;
+/// Reads a single length-prefixed frame.
+fn read_frame(reader: &mut impl Read) -> Result<Vec<u8>, String> {
+    let mut len_buf = [0u8; 4];
+    reader.read_exact(&mut len_buf).map_err(|e| format!("Failed to read frame length: {e}"))?;
 
-    if req_len > MAX_FRAME_SIZE {
-        error!("Request too large: {} bytes", req_len);
-        return None;
+    trace!("Length bytes: {:?}", len_buf);
+    let len = u32::from_le_bytes(len_buf) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(format!("Frame too large: {} bytes", len));
     }
 
-    let mut buf = vec![0u8; req_len];
-    if stream.read_exact(&mut buf).is_err() {
-        error!("Failed to read request data");
-        return None;
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf).map_err(|e| format!("Failed to read frame data: {e}"))?;
+    Ok(buf)
+}
+
+/// Writes a single length-prefixed frame and flushes it.
+fn write_frame(writer: &mut impl Write, buf: &[u8]) -> Result<(), String> {
+    if buf.len() > MAX_FRAME_SIZE {
+        return Err(format!("Frame too large: {} bytes", buf.len()));
     }
 
-    if let Ok(req) = deserialize_from_slice::<Req>(&buf) {
-        info!("Received request: {:?}", req);
-        let (tx, rx) = mpsc::channel();
-
-        std::thread::spawn(move || {
-            for response in rx {
-                trace!("Sending response: {:?}", response);
-                match serialize_to_vec(&StreamResponse::Data(response)) {
-                    Ok(resp_buf) => {
-                        if resp_buf.len() > MAX_FRAME_SIZE {
-                            error!("Response too large: {} bytes", resp_buf.len());
-                            break;
-                        }
-                        let len_bytes = (resp_buf.len() as u32).to_le_bytes();
-                        if stream.write_all(&len_bytes).is_err() {
-                            error!("Failed to send response length");
-                            break;
-                        }
-                        if stream.write_all(&resp_buf).is_err() {
-                            error!("Failed to send response data");
-                            break;
-                        }
-                        if stream.flush().is_err() {
-                            error!("Failed to flush stream");
-                            break;
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to serialize response: {}", e);
-                        break;
-                    }
-                }
-            }
+    let len_bytes = (buf.len() as u32).to_le_bytes();
+    writer.write_all(&len_bytes).map_err(|e| format!("Failed to send frame length: {e}"))?;
+    writer.write_all(buf).map_err(|e| format!("Failed to send frame data: {e}"))?;
+    writer.flush().map_err(|e| format!("Failed to flush stream: {e}"))
+}
 
-            // Send EndOfStream message
-            match serialize_to_vec(&StreamResponse::<Res>::EndOfStream) {
-                Ok(end_buf) => {
-                    let len_bytes = (end_buf.len() as u32).to_le_bytes();
-                    if stream.write_all(&len_bytes).is_err() {
-                        error!("Failed to send EndOfStream length");
-                    }
-                    if stream.write_all(&end_buf).is_err() {
-                        error!("Failed to send EndOfStream data");
-                    }
-                    let _ = stream.flush();
-                    info!("Stream ended successfully");
-                }
-                Err(e) => error!("Failed to serialize EndOfStream: {}", e),
+/// Spawns a thread that writes every response sent on the returned
+/// channel to the stream, followed by an EndOfStream once it is dropped.
+fn respond<Res>(mut stream: UnixStream) -> Sender<Res>
+where
+    Res: Serialize + Send + 'static + std::fmt::Debug,
+{
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        for response in rx {
+            trace!("Sending response: {:?}", response);
+            let sent = serialize_to_vec(&StreamResponse::Data(response))
+                .map_err(|e| format!("Failed to serialize response: {e}"))
+                .and_then(|buf| write_frame(&mut stream, &buf));
+
+            if let Err(e) = sent {
+                error!("{}", e);
+                break;
             }
-        });
+        }
+
+        // Send EndOfStream message
+        match serialize_to_vec(&StreamResponse::<Res>::EndOfStream) {
+            Ok(end_buf) => match write_frame(&mut stream, &end_buf) {
+                Ok(_) => info!("Stream ended successfully"),
+                Err(e) => error!("Failed to send EndOfStream: {}", e),
+            },
+            Err(e) => error!("Failed to serialize EndOfStream: {}", e),
+        }
+    });
+
+    tx
+}
+
+pub(crate) fn process<Res, Req>(mut stream: UnixStream) -> Option<(Req, Sender<Res>)>
+where
+    Req: for<'de> Deserialize<'de> + Send + 'static + std::fmt::Debug,
+    Res: Serialize + Send + 'static + std::fmt::Debug,
+{
+    let buf = match read_frame(&mut stream) {
+        Ok(buf) => buf,
+        Err(e) => {
+            error!("Failed to read request: {}", e);
+            return None;
+        }
+    };
 
-        Some((req, tx))
+    if let Ok(req) = deserialize_from_slice::<Req>(&buf) {
+        info!("Received request: {:?}", req);
+        Some((req, respond(stream)))
     } else {
         error!("Failed to deserialize request");
         None
     }
 }
 
+/// Requests uploaded by a client, yielded until the client
+/// sends an EndOfStream (or the connection breaks).
+pub struct Requests<Req> {
+    reader: BufReader<UnixStream>,
+    done: bool,
+    _req: PhantomData<Req>,
+}
+
+impl<Req> Iterator for Requests<Req>
+where
+    Req: for<'de> Deserialize<'de> + std::fmt::Debug,
+{
+    type Item = Req;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let req = read_frame(&mut self.reader).and_then(|buf| deserialize_from_slice::<StreamRequest<Req>>(&buf));
+        match req {
+            Ok(StreamRequest::Data(req)) => {
+                info!("Received request: {:?}", req);
+                Some(req)
+            }
+            Ok(StreamRequest::EndOfStream) => {
+                info!("End of request stream received");
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                error!("Failed to read streamed request: {}", e);
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+pub(crate) fn process_upload<Res, Req>(stream: UnixStream) -> Option<(Requests<Req>, Sender<Res>)>
+where
+    Req: for<'de> Deserialize<'de> + Send + 'static + std::fmt::Debug,
+    Res: Serialize + Send + 'static + std::fmt::Debug,
+{
+    let reader = match stream.try_clone() {
+        Ok(reader) => reader,
+        Err(e) => {
+            error!("Failed to clone stream: {}", e);
+            return None;
+        }
+    };
+
+    Some((Requests { reader: BufReader::new(reader), done: false, _req: PhantomData }, respond(stream)))
+}
+
 /// Spawns a server that listens for requests, then
 /// spawns new (std) threads to handle them.
-pub fn start_server<Req, Res, F>(socket_path: impl Into<PathBuf> + Display, handler: F) -> std::io::Result<()>
+pub fn start_server<Req, Res, F>(socket: impl Display, handler: F) -> std::io::Result<()>
 where
     Req: for<'de> Deserialize<'de> + Send + 'static + std::fmt::Debug,
     Res: Serialize + Send + 'static + std::fmt::Debug,
     F: Fn(Req, Sender<Res>) + Send + Sync + Clone + 'static,
 {
-    let socket_path = if socket_path.to_string().starts_with("/") {
-        socket_path.into()
-    } else {
-        PathBuf::from(format!("/tmp/{}.sock", socket_path))
-    };
-
+    let socket_path = socket_path(socket);
     let _ = std::fs::remove_file(&socket_path);
     let listener = UnixListener::bind(&socket_path)?;
 
@@ -170,6 +230,40 @@ where
     Ok(())
 }
 
+/// Spawns a server that accepts uploaded request streams, then
+/// spawns new (std) threads handing each one to the handler as an iterator.
+pub fn start_upload_server<Req, Res, F>(socket: impl Display, handler: F) -> std::io::Result<()>
+where
+    Req: for<'de> Deserialize<'de> + Send + 'static + std::fmt::Debug,
+    Res: Serialize + Send + 'static + std::fmt::Debug,
+    F: Fn(Requests<Req>, Sender<Res>) + Send + Sync + Clone + 'static,
+{
+    let socket_path = socket_path(socket);
+    let _ = std::fs::remove_file(&socket_path);
+    let listener = UnixListener::bind(&socket_path)?;
+
+    info!("Upload server started on {:?}", socket_path);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                info!("Accepted connection");
+                let handler = handler.clone();
+                std::thread::spawn(move || {
+                    if let Some((reqs, tx)) = process_upload(stream) {
+                        handler(reqs, tx);
+                    }
+                });
+            }
+            Err(e) => {
+                error!("Failed to accept connection: {}", e);
+                continue;
+            }
+        }
+    }
+    Ok(())
+}
+
 pub struct RequestStream<Req, Res> {
     unix_stream: tokio::net::UnixListener,
     _req: PhantomData<Req>,
@@ -227,60 +321,21 @@ where
     RequestStream::new(socket_name).await
 }
 
-pub fn send_command<Req, Res, H>(
-    socket_path: impl Into<PathBuf> + Display,
-    command: &Req,
-    handler: Option<H>,
-) -> std::io::Result<()>
+fn read_responses<Res, H>(stream: UnixStream, handler: Option<H>)
 where
-    Req: Serialize,
-    Res: for<'de> Deserialize<'de> + std::fmt::Debug, // Debug logging
+    Res: for<'de> Deserialize<'de> + std::fmt::Debug,
     H: Fn(Res) + Send + 'static,
 {
-    let socket_path = if socket_path.to_string().starts_with("/") {
-        socket_path.into()
-    } else {
-        PathBuf::from(format!("/tmp/{}.sock", socket_path))
-    };
-
-    info!("Connecting to server at {:?}", socket_path);
-    let mut stream = UnixStream::connect(&socket_path)?;
-
-    // Send request with length prefix
-    let data = serialize_to_vec(command).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-    if data.len() > MAX_FRAME_SIZE {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!("Request too large: {} bytes", data.len()),
-        ));
-    }
-    let len_bytes = (data.len() as u32).to_le_bytes();
-    stream.write_all(&len_bytes)?;
-    stream.write_all(&data)?;
-    stream.flush()?;
-    info!("Command sent");
-
     let mut reader = BufReader::new(stream);
 
     loop {
-        // Read the length of the incoming response
-        let mut len_buf = [0u8; 4];
-        if reader.read_exact(&mut len_buf).is_err() {
-            error!("Failed to read response length");
-            break;
-        }
-        let response_len = u32::from_le_bytes(len_buf) as usize;
-        if response_len > MAX_FRAME_SIZE {
-            error!("Response too large: {} bytes", response_len);
-            break;
-        }
-
-        // Read the full response
-        let mut buf = vec![0u8; response_len];
-        if reader.read_exact(&mut buf).is_err() {
-            error!("Failed to read response data");
-            break;
-        }
+        let buf = match read_frame(&mut reader) {
+            Ok(buf) => buf,
+            Err(e) => {
+                error!("Failed to read response: {}", e);
+                break;
+            }
+        };
 
         // Deserialize response
         match deserialize_from_slice::<StreamResponse<Res>>(&buf) {
@@ -300,5 +355,72 @@ where
             }
         }
     }
+}
+
+pub fn send_command<Req, Res, H>(socket: impl Display, command: &Req, handler: Option<H>) -> std::io::Result<()>
+where
+    Req: Serialize,
+    Res: for<'de> Deserialize<'de> + std::fmt::Debug, // Debug logging
+    H: Fn(Res) + Send + 'static,
+{
+    let socket_path = socket_path(socket);
+    info!("Connecting to server at {:?}", socket_path);
+    let mut stream = UnixStream::connect(&socket_path)?;
+
+    // Send request with length prefix
+    let data = serialize_to_vec(command).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    if data.len() > MAX_FRAME_SIZE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Request too large: {} bytes", data.len()),
+        ));
+    }
+    write_frame(&mut stream, &data).map_err(std::io::Error::other)?;
+    info!("Command sent");
+
+    read_responses(stream, handler);
+    Ok(())
+}
+
+/// Sends every request from the iterator to an upload server,
+/// followed by an EndOfStream, while handling responses as they arrive.
+pub fn send_upload<Req, Res, H>(
+    socket: impl Display,
+    commands: impl IntoIterator<Item = Req>,
+    handler: Option<H>,
+) -> std::io::Result<()>
+where
+    Req: Serialize,
+    Res: for<'de> Deserialize<'de> + std::fmt::Debug + Send + 'static,
+    H: Fn(Res) + Send + 'static,
+{
+    let socket_path = socket_path(socket);
+    info!("Connecting to server at {:?}", socket_path);
+    let mut stream = UnixStream::connect(&socket_path)?;
+
+    // Read responses concurrently, so a server answering mid-upload can't stall us
+    let reader = std::thread::spawn({
+        let stream = stream.try_clone()?;
+        move || read_responses(stream, handler)
+    });
+
+    for command in commands {
+        let data = serialize_to_vec(&StreamRequest::Data(command))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        if data.len() > MAX_FRAME_SIZE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Request too large: {} bytes", data.len()),
+            ));
+        }
+        write_frame(&mut stream, &data).map_err(std::io::Error::other)?;
+    }
+
+    let end = serialize_to_vec(&StreamRequest::<Req>::EndOfStream)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    write_frame(&mut stream, &end).map_err(std::io::Error::other)?;
+    info!("Upload sent");
+
+    reader.join().map_err(|_| std::io::Error::other("Response reader panicked"))?;
     Ok(())
 }
@@ -1,15 +1,15 @@
-use core::panic;
 use std::{process, time::Duration};
 
-use tokio::{select, spawn, time::sleep};
+use tokio::{select, task::spawn_blocking, time::sleep};
 
-use ipsea::{send_command, start_server};
+use ipsea::{send_command, send_upload, start_server, start_upload_server, Requests};
 
 #[tokio::main]
 async fn main() {
     pretty_env_logger::init();
 
-    let server = spawn(async {
+    // The servers and clients block, so keep them off the async workers.
+    spawn_blocking(|| {
         start_server(
             "ipsea-test",
             |i: String, o: std::sync::mpsc::Sender<String>| {
@@ -19,31 +19,56 @@ async fn main() {
         .expect("Failed to start server")
     });
 
+    spawn_blocking(|| {
+        start_upload_server(
+            "ipsea-upload-test",
+            |i: Requests<String>, o: std::sync::mpsc::Sender<usize>| {
+                o.send(i.count()).expect("Failed to count messages");
+            },
+        )
+        .expect("Failed to start upload server")
+    });
+
     sleep(Duration::from_secs(1)).await;
 
     let message: String = "Hello, world".into();
-    let (tx, mut all_good) = tokio::sync::mpsc::channel::<()>(1);
+    let (tx, mut all_good) = tokio::sync::mpsc::channel::<()>(2);
 
-    let client = spawn(async move {
-        send_command(
-            "ipsea-test",
-            &message,
-            Some({
-                let message = message.clone();
-                let tx = tx.clone();
-                move |res: String| {
-                    assert_eq!(res, message);
-                    server.abort();
-                    let _ = tx.send(());
-                }
+    spawn_blocking({
+        let tx = tx.clone();
+        move || {
+            send_command(
+                "ipsea-test",
+                &message,
+                Some({
+                    let message = message.clone();
+                    move |res: String| {
+                        assert_eq!(res, message);
+                        let _ = tx.try_send(());
+                    }
+                }),
+            )
+            .expect("Failed to send command");
+        }
+    });
+
+    let messages = vec!["Hello".to_string(), "streamed".to_string(), "world".to_string()];
+    spawn_blocking(move || {
+        send_upload(
+            "ipsea-upload-test",
+            messages.clone(),
+            Some(move |res: usize| {
+                assert_eq!(res, messages.len());
+                let _ = tx.try_send(());
             }),
         )
-        .expect("Failed to send command");
+        .expect("Failed to send upload");
     });
 
     select! {
-        _ = all_good.recv() => {
-            client.abort();
+        _ = async { all_good.recv().await; all_good.recv().await } => {
+            // The servers never return, so don't wait on them.
+            process::exit(0);
         },
         _ = sleep(Duration::from_secs(1)) => {
             eprintln!("Server took too long");
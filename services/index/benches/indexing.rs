@@ -0,0 +1,80 @@
+//! Measures how fast the indexer writes a synthetic tree of files into a fresh index,
+//! then how fast it gets through reindexing it with nothing and then a tenth changed.
+//!
+//! `cargo bench --bench indexing`, with `FINICK_BENCH_FILES` to change the tree size.
+
+use std::{
+    env, fs,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use index::settings::{self, IndexConfig, Root};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+
+const FILES_PER_DIR: usize = 100;
+
+fn main() {
+    let files = env::var("FINICK_BENCH_FILES").ok().and_then(|r| r.parse().ok()).unwrap_or(20_000);
+    let dir = env::temp_dir().join(format!("finick-bench-{}", std::process::id()));
+    let tree = dir.join("tree");
+    populate(&tree, files);
+
+    settings::set(IndexConfig {
+        roots: vec![Root::new(&tree)],
+        include_path: false,
+        max_depth: 2,
+        throttle_ms: 0,
+        idle_priority: false,
+        ..Default::default()
+    });
+    let pool = Pool::new(SqliteConnectionManager::file(dir.join("index.db"))).unwrap();
+    pool.get().unwrap().execute_batch("PRAGMA journal_mode = WAL;").unwrap();
+    index::schema::migrate(&mut pool.get().unwrap()).unwrap();
+
+    // Every file and each directory holding them
+    let entries = files + files.div_ceil(FILES_PER_DIR);
+    let taken = run(&pool, entries);
+    println!("Indexed {entries} entries in {taken:?}, {:.0} per second", entries as f64 / taken.as_secs_f64());
+
+    let taken = run(&pool, 0);
+    println!("Reindexed them unchanged in {taken:?}");
+
+    let changed = (0..files).step_by(10).inspect(|i| touch(&tree, *i)).count();
+    let taken = run(&pool, changed);
+    println!("Reindexed {changed} changed entries in {taken:?}, {:.0} per second", changed as f64 / taken.as_secs_f64());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+/// Writes `files` small files, [`FILES_PER_DIR`] to a directory.
+fn populate(tree: &Path, files: usize) {
+    for i in 0..files {
+        let dir = tree.join(format!("dir-{}", i / FILES_PER_DIR));
+        if i % FILES_PER_DIR == 0 {
+            fs::create_dir_all(&dir).unwrap();
+        }
+        fs::write(dir.join(format!("file-{i}.txt")), i.to_string()).unwrap();
+    }
+}
+
+fn touch(tree: &Path, i: usize) {
+    fs::write(tree.join(format!("dir-{}", i / FILES_PER_DIR)).join(format!("file-{i}.txt")), "changed").unwrap();
+}
+
+/// Time taken to reindex everything, until `writes` more entries are written and nothing's queued.
+fn run(pool: &Pool<SqliteConnectionManager>, writes: usize) -> Duration {
+    let target = index::schedule::progress().written + writes as u64;
+    let start = Instant::now();
+    index::index(None, pool.clone());
+
+    loop {
+        let progress = index::schedule::progress();
+        if progress.written >= target && progress.is_idle() {
+            return start.elapsed();
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,95 @@
+//! Compares substring search over file paths using the trigram index against the
+//! `LIKE '%query%'` scan it replaced, over a synthetic index.
+//!
+//! `cargo bench --bench substring`, with `FINICK_BENCH_ROWS` to change the index size.
+
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+use rusqlite::{params, Connection};
+
+const WORDS: [&str; 16] = [
+    "src", "docs", "notes", "build", "assets", "music", "photos", "projects", "config", "target", "lib", "test",
+    "finick", "release", "archive", "cache",
+];
+const EXTENSIONS: [&str; 8] = ["rs", "md", "txt", "png", "toml", "json", "jpg", "log"];
+const QUERIES: [&str; 4] = ["firefox", "notes/todo", "photos", "zzzzzz"];
+const RUNS: u32 = 20;
+
+fn main() {
+    let rows = env::var("FINICK_BENCH_ROWS").ok().and_then(|r| r.parse().ok()).unwrap_or(200_000);
+    let mut conn = Connection::open_in_memory().unwrap();
+    index::schema::migrate(&mut conn).unwrap();
+
+    let start = Instant::now();
+    populate(&conn, rows);
+    println!("Indexed {rows} paths in {:?}\n", start.elapsed());
+
+    for query in QUERIES {
+        let like = time(|| {
+            conn.query_row(
+                "SELECT count(*) FROM (SELECT path FROM files WHERE name LIKE ?1 OR path LIKE ?1 LIMIT 2000)",
+                params![format!("%{query}%")],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap()
+        });
+        let trigram = time(|| {
+            conn.query_row(
+                "SELECT count(*) FROM (SELECT rowid FROM names WHERE names MATCH ?1 LIMIT 2000)",
+                params![format!("\"{query}\"")],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap()
+        });
+
+        assert_eq!(like.1, trigram.1, "Both should find the same paths for {query:?}");
+        println!("{query:>12}: {:>6} matches, like {:>12?}, trigram {:>12?}", like.1, like.0, trigram.0);
+    }
+}
+
+/// Fills the index with `rows` paths built from a fixed pseudo-random sequence,
+/// with one in every thousand being a `firefox` and one a `notes/todo`.
+fn populate(conn: &Connection, rows: usize) {
+    let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = |n: usize| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed as usize % n
+    };
+
+    conn.execute_batch("BEGIN").unwrap();
+    let mut insert = conn
+        .prepare(
+            "INSERT INTO files (name, path, depth, executable, desktop, icon, indexed_at)
+             VALUES (?1, ?2, ?3, 0, 0, NULL, 0)",
+        )
+        .unwrap();
+
+    for i in 0..rows {
+        let depth = 1 + next(5);
+        let dirs = (0..depth).map(|_| WORDS[next(WORDS.len())]).collect::<Vec<_>>().join("/");
+        let name = match i % 1000 {
+            0 => format!("firefox-{i}"),
+            1 => format!("todo-{i}.md"),
+            _ => format!("{}_{i}.{}", WORDS[next(WORDS.len())], EXTENSIONS[next(EXTENSIONS.len())]),
+        };
+        let dirs = if i % 1000 == 1 { format!("{dirs}/notes") } else { dirs };
+
+        insert.execute(params![name, format!("/home/user/{dirs}/{name}"), depth]).unwrap();
+    }
+    conn.execute_batch("COMMIT").unwrap();
+}
+
+/// Mean time taken by `f` over [`RUNS`] runs, and its result.
+fn time<T>(mut f: impl FnMut() -> T) -> (Duration, T) {
+    let mut result = f();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        result = f();
+    }
+    (start.elapsed() / RUNS, result)
+}
@@ -0,0 +1,333 @@
+//! Parsing freedesktop Desktop Entries (`.desktop` files), following the
+//! [specification](https://specifications.freedesktop.org/desktop-entry-spec/latest/).
+//!
+//! Only the `[Desktop Entry]` group and the `[Desktop Action ...]` groups it lists are read.
+//! Localized keys like `Name[de]=` are picked by the locale in `$LC_ALL`, `$LC_MESSAGES` or
+//! `$LANG`, falling back to the unlocalized key.
+//!
+//! Entries are identified by their desktop-file ID, their path within an `applications`
+//! directory with `/` replaced by `-`. Of several with the same ID, the one in the most
+//! important directory is used, so users can override (or with `Hidden`, remove) system ones.
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// What's kept of a Desktop Entry, with every string unescaped and localized.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DesktopEntry {
+    pub name: String,
+    /// e.g. "Web Browser".
+    pub generic_name: Option<String>,
+    /// A tooltip for it.
+    pub comment: Option<String>,
+    /// An icon theme name, or an absolute path.
+    pub icon: Option<String>,
+    /// The command line it launches, see [`DesktopEntry::command`].
+    pub exec: Option<String>,
+    /// A program which must be installed for it to be shown.
+    pub try_exec: Option<String>,
+    /// Directory to launch it in.
+    pub working_dir: Option<String>,
+    /// Whether it runs in a terminal.
+    pub terminal: bool,
+    pub categories: Vec<String>,
+    /// Further words it's searched by.
+    pub keywords: Vec<String>,
+    /// Desktops (as in `$XDG_CURRENT_DESKTOP`) it's only shown in, or every one if empty.
+    pub only_show_in: Vec<String>,
+    /// Desktops it isn't shown in.
+    pub not_show_in: Vec<String>,
+    /// Whether it's installed but not meant to be shown, e.g. a handler for some MIME type.
+    pub no_display: bool,
+    /// Whether it's been deleted, usually to hide one of the same name in a system directory.
+    pub hidden: bool,
+    pub actions: Vec<Action>,
+}
+
+/// Something it can be launched to do besides the default, e.g. opening a private window.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Action {
+    pub id: String,
+    pub name: String,
+    pub icon: Option<String>,
+    pub exec: Option<String>,
+}
+
+impl DesktopEntry {
+    /// Reads the entry at `path`, localized for the current locale.
+    pub fn read(path: &Path) -> Option<Self> { Self::parse(&fs::read_to_string(path).ok()?, locale().as_deref()) }
+
+    /// Parses `source`, localized for `locale` (as in `lang_COUNTRY.ENCODING@MODIFIER`),
+    /// or `None` if it has no `[Desktop Entry]` group.
+    pub fn parse(source: &str, locale: Option<&str>) -> Option<Self> {
+        let groups = groups(source);
+        let entry = &groups.iter().find(|(name, _)| *name == "Desktop Entry")?.1;
+        let locales = locale.map(locale_keys).unwrap_or_default();
+
+        let actions = entry
+            .list("Actions", &[])
+            .into_iter()
+            .filter_map(|id| {
+                let (_, group) = groups.iter().find(|(name, _)| name.strip_prefix("Desktop Action ") == Some(&id))?;
+                Some(Action {
+                    name: group.string("Name", &locales)?,
+                    icon: group.string("Icon", &locales),
+                    exec: group.string("Exec", &[]),
+                    id,
+                })
+            })
+            .collect();
+
+        Some(DesktopEntry {
+            name: entry.string("Name", &locales).unwrap_or_default(),
+            generic_name: entry.string("GenericName", &locales),
+            comment: entry.string("Comment", &locales),
+            icon: entry.string("Icon", &locales),
+            exec: entry.string("Exec", &[]),
+            try_exec: entry.string("TryExec", &[]),
+            working_dir: entry.string("Path", &[]),
+            terminal: entry.bool("Terminal"),
+            categories: entry.list("Categories", &[]),
+            keywords: entry.list("Keywords", &locales),
+            only_show_in: entry.list("OnlyShowIn", &[]),
+            not_show_in: entry.list("NotShowIn", &[]),
+            no_display: entry.bool("NoDisplay"),
+            hidden: entry.bool("Hidden"),
+            actions,
+        })
+    }
+
+    /// Whether it should be shown: it isn't hidden, it's meant for the current desktop
+    /// and its `TryExec` program is installed.
+    pub fn is_shown(&self) -> bool {
+        let current = env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+        let current = current.split(':').filter(|d| !d.is_empty()).collect::<Vec<_>>();
+        let in_current = |desktops: &[String]| desktops.iter().any(|d| current.contains(&d.as_str()));
+
+        !self.hidden
+            && !self.no_display
+            && (self.only_show_in.is_empty() || in_current(&self.only_show_in))
+            && !in_current(&self.not_show_in)
+            && self.try_exec.as_deref().is_none_or(is_installed)
+    }
+
+    /// What it's searched by besides its path: its name, generic name and keywords.
+    pub fn terms(&self) -> String {
+        let terms = std::iter::once(&self.name).chain(&self.generic_name).chain(&self.keywords);
+        terms.map(String::as_str).collect::<Vec<_>>().join(" ")
+    }
+
+    /// The command line launching it with `files` (paths or URLs), or `None` if it has no `Exec`.
+    pub fn command(&self, files: &[&str]) -> Option<Vec<String>> {
+        expand(self.exec.as_deref()?, &self.name, self.icon.as_deref(), files)
+    }
+}
+
+impl Action {
+    /// The command line launching it with `files`, like [`DesktopEntry::command`].
+    pub fn command(&self, files: &[&str]) -> Option<Vec<String>> {
+        expand(self.exec.as_deref()?, &self.name, self.icon.as_deref(), files)
+    }
+}
+
+/// Where entries are installed, most important first: `applications` in `$XDG_DATA_HOME`,
+/// then in each of `$XDG_DATA_DIRS`.
+pub fn application_dirs() -> Vec<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME").map(PathBuf::from).or_else(|| Some(env::home_dir()?.join(".local/share")));
+    let data_dirs = env::var("XDG_DATA_DIRS").ok().filter(|d| !d.is_empty());
+    let data_dirs = data_dirs.as_deref().unwrap_or("/usr/local/share:/usr/share").split(':').map(PathBuf::from);
+    data_home.into_iter().chain(data_dirs).map(|dir| dir.join("applications")).collect()
+}
+
+/// Whether the entry at `path` is overridden by one with the same desktop-file ID in a more
+/// important one of the [`application_dirs`].
+pub fn is_overridden(path: &Path) -> bool {
+    let dirs = application_dirs();
+    let id = dirs.iter().enumerate().find_map(|(i, dir)| Some((i, path.strip_prefix(dir).ok()?)));
+    let Some((at, relative)) = id else { return false };
+
+    let id = relative.iter().map(|part| part.to_string_lossy()).collect::<Vec<_>>().join("-");
+    dirs[..at].iter().any(|dir| has_id(dir, &id))
+}
+
+/// Whether there's an entry with the desktop-file ID `id` in `dir` or below it.
+fn has_id(dir: &Path, id: &str) -> bool {
+    dir.join(id).is_file()
+        || id.match_indices('-').any(|(i, _)| dir.join(&id[..i]).is_dir() && has_id(&dir.join(&id[..i]), &id[i + 1..]))
+}
+
+/// The current locale from the environment, if any.
+pub fn locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"].into_iter().filter_map(|var| env::var(var).ok()).find(|l| !l.is_empty())
+}
+
+/// Locales whose keys apply to `locale`, best first: `sr_YU.UTF-8@Latn` looks for
+/// `sr_YU@Latn`, `sr_YU`, `sr@Latn` and then `sr`.
+fn locale_keys(locale: &str) -> Vec<String> {
+    let (rest, modifier) = locale.split_once('@').map_or((locale, None), |(rest, m)| (rest, Some(m)));
+    let rest = rest.split('.').next().unwrap_or(rest);
+    let (lang, country) = rest.split_once('_').map_or((rest, None), |(lang, c)| (lang, Some(c)));
+
+    let mut keys = vec![];
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        keys.push(format!("{lang}_{country}@{modifier}"));
+    }
+    if let Some(country) = country {
+        keys.push(format!("{lang}_{country}"));
+    }
+    if let Some(modifier) = modifier {
+        keys.push(format!("{lang}@{modifier}"));
+    }
+    keys.push(lang.to_string());
+    keys
+}
+
+/// A group's entries, as their key, locale and raw value.
+pub(crate) struct Group<'a>(Vec<(&'a str, Option<&'a str>, &'a str)>);
+
+impl<'a> Group<'a> {
+    /// The raw value of `key` in the first of `locales` it has, or else unlocalized.
+    fn raw(&self, key: &str, locales: &[String]) -> Option<&'a str> {
+        let find = |locale: Option<&str>| self.0.iter().find(|(k, l, _)| *k == key && *l == locale).map(|(.., v)| *v);
+        locales.iter().find_map(|l| find(Some(l))).or_else(|| find(None))
+    }
+
+    pub(crate) fn string(&self, key: &str, locales: &[String]) -> Option<String> { self.raw(key, locales).map(unescape) }
+
+    /// A `;` separated list, where `\;` is a literal `;`.
+    fn list(&self, key: &str, locales: &[String]) -> Vec<String> {
+        let Some(raw) = self.raw(key, locales) else { return vec![] };
+        let mut items = vec![String::new()];
+        let mut chars = raw.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(';') => items.last_mut().unwrap().push(';'),
+                    Some(next) => items.last_mut().unwrap().extend(['\\', next]),
+                    None => {}
+                },
+                ';' => items.push(String::new()),
+                c => items.last_mut().unwrap().push(c),
+            }
+        }
+        items.iter().filter(|item| !item.is_empty()).map(|item| unescape(item)).collect()
+    }
+
+    fn bool(&self, key: &str) -> bool { self.raw(key, &[]) == Some("true") }
+}
+
+/// Every group of `source` by name, in order. Icon themes and GTK's settings share the format.
+pub(crate) fn groups(source: &str) -> Vec<(&str, Group<'_>)> {
+    let mut groups: Vec<(&str, Group)> = vec![];
+    for line in source.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            groups.push((name, Group(vec![])));
+        } else if let (Some((key, value)), Some((_, group))) = (line.split_once('='), groups.last_mut()) {
+            let key = key.trim_end();
+            let (key, locale) = match key.split_once('[') {
+                Some((key, locale)) => (key, locale.strip_suffix(']')),
+                None => (key, None),
+            };
+            group.0.push((key, locale, value.trim_start()));
+        }
+    }
+    groups
+}
+
+/// Undoes the escapes allowed in values: `\s`, `\n`, `\t`, `\r` and `\\`.
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('s') => unescaped.push(' '),
+                Some('n') => unescaped.push('\n'),
+                Some('t') => unescaped.push('\t'),
+                Some('r') => unescaped.push('\r'),
+                Some(other) => unescaped.push(other),
+                None => unescaped.push('\\'),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Splits `exec` into arguments and expands its field codes: `%f` and `%u` to the first of
+/// `files`, `%F` and `%U` to all of them, `%i` to the icon, `%c` to the name and `%%` to `%`.
+/// Deprecated and unknown codes are dropped.
+fn expand(exec: &str, name: &str, icon: Option<&str>, files: &[&str]) -> Option<Vec<String>> {
+    let mut command = vec![];
+    for arg in split_exec(exec) {
+        match arg.as_str() {
+            "%f" | "%u" => command.extend(files.first().map(|f| f.to_string())),
+            "%F" | "%U" => command.extend(files.iter().map(|f| f.to_string())),
+            "%i" => command.extend(icon.into_iter().flat_map(|icon| ["--icon".to_string(), icon.to_string()])),
+            _ => {
+                let mut expanded = String::new();
+                let mut chars = arg.chars();
+                while let Some(c) = chars.next() {
+                    match (c, chars.clone().next()) {
+                        ('%', Some(code)) => {
+                            chars.next();
+                            match code {
+                                '%' => expanded.push('%'),
+                                'c' => expanded.push_str(name),
+                                'f' | 'u' => expanded.push_str(files.first().copied().unwrap_or_default()),
+                                _ => {}
+                            }
+                        }
+                        (c, _) => expanded.push(c),
+                    }
+                }
+                // Unless it was only a dropped code
+                if !expanded.is_empty() || arg.is_empty() {
+                    command.push(expanded);
+                }
+            }
+        }
+    }
+    (!command.is_empty()).then_some(command)
+}
+
+/// Splits `exec` into arguments at spaces, except within double quotes, where `\` escapes
+/// any of `"`, `` ` ``, `$` and `\`.
+fn split_exec(exec: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut arg = None::<String>;
+    let mut quoted = false;
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                arg.get_or_insert_default();
+            }
+            '\\' if quoted => {
+                let next = chars.next().unwrap_or('\\');
+                if !matches!(next, '"' | '`' | '$' | '\\') {
+                    arg.get_or_insert_default().push('\\');
+                }
+                arg.get_or_insert_default().push(next);
+            }
+            c if c.is_whitespace() && !quoted => args.extend(arg.take()),
+            c => arg.get_or_insert_default().push(c),
+        }
+    }
+    args.extend(arg);
+    args
+}
+
+/// Whether `program` is an executable, either at its path or in `$PATH`.
+fn is_installed(program: &str) -> bool {
+    let executable = |path: &Path| path.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0);
+    match program.contains('/') {
+        true => executable(Path::new(program)),
+        false => env::var_os("PATH").is_some_and(|paths| env::split_paths(&paths).any(|dir| executable(&dir.join(program)))),
+    }
+}
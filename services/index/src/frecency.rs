@@ -0,0 +1,45 @@
+//! Ranking files by how often and how recently they're opened.
+//!
+//! Each [`Request::Opened`](crate::ty::Request::Opened) bumps a file's count in the `opens`
+//! table, and its frecency is that count decayed by how long ago it was last opened, halving
+//! every [`HALF_LIFE`]. Searches add a [`bonus`] for it to fuzzy scores, so a file opened
+//! often lately beats a slightly closer match that never is.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use rusqlite::{params, Connection};
+
+/// How long it takes a file's frecency to halve since it was last opened.
+const HALF_LIFE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Fuzzy score a file opened once just now is worth, growing logarithmically with frecency.
+const WEIGHT: f64 = 12.0;
+
+/// Counts `path` as opened just now.
+pub fn record(conn: &Connection, path: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO opens (path, count, last_opened) VALUES (?1, 1, ?2)
+         ON CONFLICT (path) DO UPDATE SET count = count + 1, last_opened = excluded.last_opened",
+        params![path, now()],
+    )?;
+    Ok(())
+}
+
+/// The frecency of every file ever opened, by path.
+pub fn all(conn: &Connection) -> rusqlite::Result<HashMap<String, f64>> {
+    let now = now();
+    let mut stmt = conn.prepare_cached("SELECT path, count, last_opened FROM opens")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, score(row.get(1)?, row.get(2)?, now))))?;
+    rows.collect()
+}
+
+/// `count` opens, decayed by how long before `now` the last was.
+pub fn score(count: i64, last_opened: i64, now: i64) -> f64 {
+    let age = (now - last_opened).max(0) as f64;
+    count as f64 * 0.5f64.powf(age / HALF_LIFE.as_secs_f64())
+}
+
+/// What a frecency adds to a fuzzy score.
+pub fn bonus(frecency: f64) -> i64 { (WEIGHT * frecency.ln_1p() / 2f64.ln()).round() as i64 }
+
+fn now() -> i64 { SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs() as i64 }
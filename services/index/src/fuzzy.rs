@@ -0,0 +1,132 @@
+//! Fuzzy matching of queries against file names and paths.
+//!
+//! A query matches if its characters appear in order (case-insensitively) in the
+//! candidate, so `ffx` matches `firefox`. Matches are scored so that tighter and
+//! better placed ones rank higher: consecutive runs, matches at the start of words,
+//! camelCase humps and path segments, and matches at the very start all earn bonuses,
+//! while gaps between matched characters cost a little.
+
+/// Every matched character.
+const MATCH: i64 = 16;
+/// Matching the first character of the candidate.
+const PREFIX: i64 = 12;
+/// Matching the first character after a `/`.
+const SEGMENT: i64 = 10;
+/// Matching the first character after another separator, e.g. `_`, `-`, `.` or a space.
+const BOUNDARY: i64 = 8;
+/// Matching an uppercase character following a lowercase one.
+const CAMEL: i64 = 7;
+/// Matching the character right after the previous match.
+const CONSECUTIVE: i64 = 5;
+/// Matching with the same case as the query.
+const EXACT_CASE: i64 = 1;
+/// Starting a gap between matches.
+const GAP_START: i64 = 3;
+/// Each further skipped character in a gap.
+const GAP_EXTEND: i64 = 1;
+/// Matching the file name rather than somewhere in the rest of its path.
+const NAME: i64 = 20;
+/// Only matching once a character of the query has been dropped.
+const TYPO: i64 = 30;
+/// Shortest query allowed a typo, shorter ones would match nearly anything.
+const TYPO_MIN_LENGTH: usize = 4;
+
+/// Scores `query` against `candidate`, or `None` if it isn't a subsequence of it.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let query = query.chars().collect::<Vec<_>>();
+    let candidate = candidate.chars().collect::<Vec<_>>();
+    if query.is_empty() || query.len() > candidate.len() {
+        return None;
+    }
+
+    let bonuses = (0..candidate.len()).map(|j| bonus(&candidate, j)).collect::<Vec<_>>();
+
+    // best[j]: best score for the query so far, with its last character matched at j
+    let mut best: Vec<Option<i64>> = vec![None; candidate.len()];
+    for (i, q) in query.iter().enumerate() {
+        let mut next: Vec<Option<i64>> = vec![None; candidate.len()];
+        // Best score available to a match at j after a gap, from a match at or before j - 2
+        let mut gapped: Option<i64> = None;
+
+        for j in 0..candidate.len() {
+            if j >= 2 {
+                gapped = match (gapped, best[j - 2]) {
+                    (Some(g), Some(b)) => Some((g - GAP_EXTEND).max(b - GAP_START)),
+                    (g, b) => g.map(|g| g - GAP_EXTEND).or(b.map(|b| b - GAP_START)),
+                };
+            }
+
+            let c = candidate[j];
+            if !c.to_lowercase().eq(q.to_lowercase()) {
+                continue;
+            }
+
+            let own = MATCH + bonuses[j] + if c == *q { EXACT_CASE } else { 0 };
+            next[j] = if i == 0 {
+                // Anything before the first match is a gap too, just a cheap one
+                Some(own - (j as i64).min(GAP_START))
+            } else {
+                let consecutive = j.checked_sub(1).and_then(|k| best[k]).map(|s| s + CONSECUTIVE);
+                match (consecutive, gapped) {
+                    (Some(a), Some(b)) => Some(a.max(b) + own),
+                    (a, b) => a.or(b).map(|s| s + own),
+                }
+            };
+        }
+
+        best = next;
+    }
+
+    best.into_iter().flatten().max()
+}
+
+fn bonus(candidate: &[char], j: usize) -> i64 {
+    let Some(prev) = j.checked_sub(1).map(|k| candidate[k]) else { return PREFIX };
+    let c = candidate[j];
+
+    if prev == '/' {
+        SEGMENT
+    } else if matches!(prev, '_' | '-' | '.' | ' ') && c.is_alphanumeric() {
+        BOUNDARY
+    } else if prev.is_lowercase() && c.is_uppercase() || !prev.is_ascii_digit() && c.is_ascii_digit() {
+        CAMEL
+    } else {
+        0
+    }
+}
+
+/// Scores a file by its name and path, preferring matches within the name.
+/// Longer queries which don't match at all are retried with each character
+/// dropped in turn, so single typos still find something.
+pub fn rank(query: &str, name: &str, path: &str) -> Option<i64> {
+    let exact = |query: &str| {
+        let name = score(query, name).map(|s| s + NAME);
+        let path = score(query, path);
+        name.max(path)
+    };
+
+    exact(query).or_else(|| {
+        typo_variants(query).filter_map(|q| exact(&q)).max().map(|s| s - TYPO)
+    })
+}
+
+/// The query with each character dropped in turn, if it's long enough to allow a typo.
+pub fn typo_variants(query: &str) -> impl Iterator<Item = String> + '_ {
+    let chars = query.chars().collect::<Vec<_>>();
+    let count = if chars.len() >= TYPO_MIN_LENGTH { chars.len() } else { 0 };
+
+    (0..count).map(move |skip| chars.iter().enumerate().filter(|(i, _)| *i != skip).map(|(_, c)| c).collect())
+}
+
+/// A `LIKE` pattern (escaped with `\`) matching strings which contain the query as a subsequence.
+pub fn like_pattern(query: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in query.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+        pattern.push('%');
+    }
+    pattern
+}
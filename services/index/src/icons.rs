@@ -0,0 +1,206 @@
+//! Finding icons by name, following the freedesktop
+//! [icon theme specification](https://specifications.freedesktop.org/icon-theme-spec/latest/).
+//!
+//! Icons are looked up in the configured theme, then the themes it inherits from, then
+//! `hicolor`, and finally loose in the base directories. Within a theme, a directory made
+//! for the size asked for wins, otherwise the closest size does.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use image::{DynamicImage, ImageReader, RgbaImage};
+use resvg::{tiny_skia, usvg};
+
+use crate::desktop::groups;
+use crate::settings;
+
+/// The theme every other falls back to.
+const FALLBACK_THEME: &str = "hicolor";
+/// Icon formats in order of preference. XPM is in the spec too, but can't be decoded.
+const EXTENSIONS: [&str; 2] = ["png", "svg"];
+
+/// Themes by name, as read from their `index.theme`. Those not installed are looked for
+/// again each time, in case they since have been.
+static THEMES: LazyLock<Mutex<HashMap<String, Arc<Theme>>>> = LazyLock::new(Default::default);
+
+struct Theme {
+    name: String,
+    inherits: Vec<String>,
+    directories: Vec<Directory>,
+}
+
+/// A subdirectory of a theme, holding icons of some size.
+struct Directory {
+    path: String,
+    size: u32,
+    scale: u32,
+    kind: Kind,
+    min_size: u32,
+    max_size: u32,
+    threshold: u32,
+}
+
+enum Kind {
+    Fixed,
+    Scalable,
+    Threshold,
+}
+
+/// The file of icon `name` in `theme` best suited to `size` pixels at `scale`, or `name`
+/// itself if it's an absolute path.
+pub fn find(name: &str, theme: &str, size: u32, scale: u32) -> Option<PathBuf> {
+    if Path::new(name).is_absolute() {
+        return Path::new(name).is_file().then(|| PathBuf::from(name));
+    }
+
+    let mut visited = vec![];
+    find_in(theme, name, size, scale, &mut visited)
+        .or_else(|| find_in(FALLBACK_THEME, name, size, scale, &mut visited))
+        .or_else(|| {
+            let mut loose = base_dirs().into_iter().chain([PathBuf::from("/usr/share/pixmaps")]);
+            loose.find_map(|dir| EXTENSIONS.iter().map(|ext| dir.join(format!("{name}.{ext}"))).find(|p| p.is_file()))
+        })
+}
+
+/// Reads the icon at `path` as an image fitting `size` pixels square, rendering it if it's an SVG.
+pub fn load(path: &Path, size: u32) -> Option<DynamicImage> {
+    if path.extension().is_none_or(|ext| ext != "svg") {
+        let img = ImageReader::open(path).ok()?.decode().ok()?;
+        return Some(img.resize(size, size, image::imageops::FilterType::Lanczos3));
+    }
+
+    let tree = usvg::Tree::from_data(&fs::read(path).ok()?, &usvg::Options::default()).ok()?;
+    let scale = size as f32 / tree.size().width().max(tree.size().height());
+    let (width, height) = ((tree.size().width() * scale).ceil() as u32, (tree.size().height() * scale).ceil() as u32);
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)?;
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    // Pixmaps are premultiplied, images aren't
+    let pixels = pixmap.pixels().iter().map(|p| p.demultiply()).flat_map(|c| [c.red(), c.green(), c.blue(), c.alpha()]);
+    RgbaImage::from_raw(width, height, pixels.collect()).map(DynamicImage::ImageRgba8)
+}
+
+/// Looks `name` up in `theme` and then those it inherits from, skipping any already `visited`.
+fn find_in(theme: &str, name: &str, size: u32, scale: u32, visited: &mut Vec<String>) -> Option<PathBuf> {
+    if visited.iter().any(|v| v == theme) {
+        return None;
+    }
+    visited.push(theme.to_string());
+
+    let theme = load_theme(theme)?;
+    lookup(&theme, name, size, scale).or_else(|| theme.inherits.iter().find_map(|parent| find_in(parent, name, size, scale, visited)))
+}
+
+/// The file of `name` in `theme` itself, from a directory for `size` at `scale` if there is
+/// one, else from the one closest to it.
+fn lookup(theme: &Theme, name: &str, size: u32, scale: u32) -> Option<PathBuf> {
+    let roots = base_dirs().into_iter().map(|dir| dir.join(&theme.name)).filter(|dir| dir.is_dir()).collect::<Vec<_>>();
+    let file_in = |dir: &Directory| {
+        let files = roots.iter().flat_map(|root| EXTENSIONS.map(|ext| root.join(&dir.path).join(format!("{name}.{ext}"))));
+        files.into_iter().find(|file| file.is_file())
+    };
+
+    if let Some(file) = theme.directories.iter().filter(|dir| dir.matches(size, scale)).find_map(file_in) {
+        return Some(file);
+    }
+
+    let closest = theme.directories.iter().filter_map(|dir| Some((dir.distance(size, scale), file_in(dir)?)));
+    closest.min_by_key(|(distance, _)| *distance).map(|(_, file)| file)
+}
+
+impl Directory {
+    fn matches(&self, size: u32, scale: u32) -> bool {
+        self.scale == scale
+            && match self.kind {
+                Kind::Fixed => self.size == size,
+                Kind::Scalable => (self.min_size..=self.max_size).contains(&size),
+                Kind::Threshold => (self.size.saturating_sub(self.threshold)..=self.size + self.threshold).contains(&size),
+            }
+    }
+
+    /// How far its icons are from `size` at `scale`, in pixels.
+    fn distance(&self, size: u32, scale: u32) -> u32 {
+        let wanted = size * scale;
+        let (min, max) = match self.kind {
+            Kind::Fixed => (self.size, self.size),
+            Kind::Scalable => (self.min_size, self.max_size),
+            Kind::Threshold => (self.size.saturating_sub(self.threshold), self.size + self.threshold),
+        };
+        let (min, max) = (min * self.scale, max * self.scale);
+        min.saturating_sub(wanted) + wanted.saturating_sub(max)
+    }
+}
+
+/// The theme named `name`, from the first base directory with an `index.theme` for it.
+fn load_theme(name: &str) -> Option<Arc<Theme>> {
+    if let Some(theme) = THEMES.lock().unwrap().get(name) {
+        return Some(theme.clone());
+    }
+
+    let index = base_dirs().into_iter().find_map(|dir| fs::read_to_string(dir.join(name).join("index.theme")).ok())?;
+    let theme = Arc::new(parse_theme(name, &index)?);
+    THEMES.lock().unwrap().insert(name.to_string(), theme.clone());
+    Some(theme)
+}
+
+fn parse_theme(name: &str, index: &str) -> Option<Theme> {
+    let groups = groups(index);
+    let theme = &groups.iter().find(|(group, _)| *group == "Icon Theme")?.1;
+    let comma_list = |key| {
+        let list = theme.string(key, &[]).unwrap_or_default();
+        list.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect::<Vec<_>>()
+    };
+
+    let directories = comma_list("Directories")
+        .into_iter()
+        .chain(comma_list("ScaledDirectories"))
+        .filter_map(|path| {
+            let (_, group) = groups.iter().find(|(group, _)| *group == path)?;
+            let number = |key| group.string(key, &[]).and_then(|n| n.parse().ok());
+            let size = number("Size")?;
+            Some(Directory {
+                size,
+                scale: number("Scale").unwrap_or(1),
+                kind: match group.string("Type", &[]).as_deref() {
+                    Some("Fixed") => Kind::Fixed,
+                    Some("Scalable") => Kind::Scalable,
+                    _ => Kind::Threshold,
+                },
+                min_size: number("MinSize").unwrap_or(size),
+                max_size: number("MaxSize").unwrap_or(size),
+                threshold: number("Threshold").unwrap_or(2),
+                path,
+            })
+        })
+        .collect();
+
+    Some(Theme { name: name.to_string(), inherits: comma_list("Inherits"), directories })
+}
+
+/// The configured theme, else GTK's, else [`FALLBACK_THEME`]. Reads GTK's settings, so
+/// it's best looked up once and passed to [`find`].
+pub fn current_theme() -> String {
+    settings::current().icon_theme.clone().or_else(gtk_theme).unwrap_or_else(|| FALLBACK_THEME.to_string())
+}
+
+/// The icon theme set in GTK's `settings.ini`.
+fn gtk_theme() -> Option<String> {
+    let config = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from).or_else(|| Some(env::home_dir()?.join(".config")))?;
+    let source = fs::read_to_string(config.join("gtk-3.0/settings.ini")).ok()?;
+    let groups = groups(&source);
+    groups.iter().find(|(group, _)| *group == "Settings")?.1.string("gtk-icon-theme-name", &[])
+}
+
+/// Where themes are looked for, in order: `~/.icons`, then `icons` in each XDG data directory.
+fn base_dirs() -> Vec<PathBuf> {
+    let home = env::home_dir();
+    let data_home = env::var_os("XDG_DATA_HOME").map(PathBuf::from).or_else(|| Some(home.clone()?.join(".local/share")));
+    let data_dirs = env::var("XDG_DATA_DIRS").ok().filter(|d| !d.is_empty());
+    let data_dirs = data_dirs.as_deref().unwrap_or("/usr/local/share:/usr/share").split(':').map(PathBuf::from);
+
+    let icons = data_home.into_iter().chain(data_dirs).map(|dir| dir.join("icons"));
+    home.map(|home| home.join(".icons")).into_iter().chain(icons).collect()
+}
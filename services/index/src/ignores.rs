@@ -0,0 +1,89 @@
+//! Ignore files, read hierarchically while walking, as git and ripgrep do.
+//!
+//! Each directory's `.gitignore`, `.ignore` and `.finickignore` apply to everything under it.
+//! Deeper files take precedence over shallower ones, and within a directory later files in
+//! that list over earlier ones, so a `.finickignore` can re-include (`!pattern`) whatever a
+//! `.gitignore` beside it excludes. Under all of them sit the `ignore_globs` setting, then git's
+//! global excludes file (`core.excludesFile`), which apply everywhere.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ipsea::log::error;
+
+use crate::settings::IndexConfig;
+
+/// Names of the ignore files read in each directory, in increasing precedence.
+pub const FILES: [&str; 3] = [".gitignore", ".ignore", ".finickignore"];
+
+/// The ignore rules in effect in a directory.
+#[derive(Clone)]
+pub struct Ignores {
+    /// Whether to read ignore files, rather than only the global globs.
+    files: bool,
+    /// Matchers from the shallowest directory to the deepest.
+    stack: Vec<Arc<Gitignore>>,
+}
+
+impl Ignores {
+    /// The rules applying everywhere, for the top of a root.
+    pub fn new(settings: &IndexConfig) -> Self {
+        let (global, e) = GitignoreBuilder::new("/").build_global();
+        if let Some(e) = e {
+            error!("Failed to read global ignores: {e}");
+        }
+
+        let mut builder = GitignoreBuilder::new("/");
+        for glob in &settings.ignore_globs {
+            // Invalid globs are rejected by validation.
+            let _ = builder.add_line(None, glob);
+        }
+        let globs = builder.build().unwrap_or_else(|_| Gitignore::empty());
+
+        Self { files: settings.ignore_files, stack: vec![Arc::new(global), Arc::new(globs)] }
+    }
+
+    /// The rules in `dir`, reading whatever ignore files it has.
+    pub fn enter(&self, dir: &Path) -> Self {
+        let present = FILES.map(|name| dir.join(name)).into_iter().filter(|p| self.files && p.is_file()).collect::<Vec<_>>();
+        if present.is_empty() {
+            return self.clone();
+        }
+
+        let mut builder = GitignoreBuilder::new(dir);
+        for path in present {
+            if let Some(e) = builder.add(&path) {
+                error!("Failed to read {}: {e}", path.display());
+            }
+        }
+
+        let mut ignores = self.clone();
+        match builder.build() {
+            Ok(matcher) => ignores.stack.push(Arc::new(matcher)),
+            Err(e) => error!("Failed to read ignore files in {}: {e}", dir.display()),
+        }
+        ignores
+    }
+
+    /// The rules in `dir`, reading the ignore files of every directory between it and `root`,
+    /// or `None` if `dir` is itself ignored along the way.
+    pub fn within(settings: &IndexConfig, root: &Path, dir: &Path) -> Option<Self> {
+        let mut ignores = Self::new(settings).enter(root);
+        let mut at = root.to_path_buf();
+        for component in dir.strip_prefix(root).ok()?.components() {
+            at.push(component);
+            if ignores.is_ignored(&at, true) {
+                return None;
+            }
+            ignores = ignores.enter(&at);
+        }
+
+        Some(ignores)
+    }
+
+    /// Whether `path`, directly within the directory these rules are for, is ignored.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.stack.iter().rev().map(|m| m.matched(path, is_dir)).find(|m| !m.is_none()).is_some_and(|m| m.is_ignore())
+    }
+}
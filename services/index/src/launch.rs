@@ -0,0 +1,72 @@
+//! Opening files for clients of the index service, which are told about it so they rank
+//! higher in later searches, see [`crate::frecency`].
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{self, Path};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use config::ty::App;
+use ipsea::log::error;
+
+use crate::desktop::DesktopEntry;
+use crate::ty::{Request, Response};
+
+/// Opens `path` how the desktop would: desktop entries are launched, executables run and
+/// anything else opened with its default application. The index service is then told it
+/// was opened, under the path it was given rather than where any symlink points.
+pub fn open(path: &Path) -> io::Result<()> {
+    let path = path::absolute(path)?;
+    let metadata = path.metadata()?;
+
+    let entry = path.extension().is_some_and(|ext| ext == "desktop").then(|| DesktopEntry::read(&path)).flatten();
+    let mut command = if let Some(entry) = entry {
+        launcher(&path, &entry)
+    } else if metadata.is_file() && metadata.permissions().mode() & 0o111 != 0 && is_program(&path) {
+        Command::new(&path)
+    } else {
+        let mut command = Command::new("xdg-open");
+        command.arg(&path);
+        command
+    };
+    let mut child = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
+    // Reaped once it exits, so long-running callers don't collect zombies. Short-lived ones
+    // may exit first, leaving it to init.
+    thread::spawn(move || child.wait());
+
+    let request = Request::Opened { path: path.to_string_lossy().to_string() };
+    if let Err(e) = ipsea::send_command(App::IndexService, &request, None::<fn(Response)>) {
+        error!("Failed to tell the index {} was opened: {e}", path.display());
+    }
+    Ok(())
+}
+
+/// Whether `path` is a binary or a script rather than, say, a document that happens to be
+/// executable (as everything on some mounted filesystems is).
+fn is_program(path: &Path) -> bool {
+    let mut magic = [0; 4];
+    let Ok(read) = File::open(path).and_then(|mut file| file.read(&mut magic)) else { return false };
+    magic[..read].starts_with(b"\x7fELF") || magic[..read].starts_with(b"#!")
+}
+
+/// Runs `entry`'s `Exec` in its working directory, or leaves it to `gio` for those which
+/// need a terminal, as which one to use is the desktop's choice.
+fn launcher(path: &Path, entry: &DesktopEntry) -> Command {
+    match entry.command(&[]).filter(|_| !entry.terminal) {
+        Some(args) => {
+            let mut command = Command::new(&args[0]);
+            command.args(&args[1..]);
+            if let Some(dir) = &entry.working_dir {
+                command.current_dir(dir);
+            }
+            command
+        }
+        None => {
+            let mut command = Command::new("gio");
+            command.arg("launch").arg(path);
+            command
+        }
+    }
+}
@@ -1,185 +1,504 @@
-use image::ImageReader;
-use ipsea::log::trace;
-use notify::{Config, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use desktop::DesktopEntry;
+use ignores::Ignores;
+use schedule::Rank;
+use ipsea::log::{error, info, trace};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use regex::Regex;
-use rusqlite::params;
+use query::{Filter, ParseError, Query};
+use rusqlite::{params, types::Value, Connection, Row};
+use settings::IndexConfig;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::env::{self, var};
-use std::fs::{self, read_dir};
-use std::os::unix::fs::PermissionsExt;
+use std::fmt::{self, Display};
+use std::fs::{self, FileType};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
 use std::path::Path;
-use std::sync::{mpsc, Arc};
-use std::time::Duration;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
 use std::{path::PathBuf, time::SystemTime};
-use ty::SearchResult;
+use ty::{Kind, Metadata, Scope, SearchResult, Snippet};
 
 struct ChannelData {
     name: String,
     path_str: String,
+    parent: String,
     timestamp: i64,
     depth: usize,
+    metadata: Metadata,
     executable: bool,
     is_desktop: bool,
     icon: Option<String>,
+    entry: Option<DesktopEntry>,
+    contents: Option<String>,
 }
 
+/// A change for the writer thread to make to the index.
+enum Change {
+    Added(ChannelData),
+    Updated(ChannelData),
+    /// A path which vanished, along with anything under it.
+    Removed(String),
+}
+
+/// What entries are compared by, to tell whether they changed since being indexed.
+type Stamp = (Kind, i64, i64, u64, u64);
+
+fn stamp(m: &Metadata) -> Stamp { (m.kind, m.modified, m.changed, m.size, m.inode) }
+
+/// Longest snippet line sent back to clients.
+const SNIPPET_LENGTH: usize = 200;
+/// How many prefiltered candidates are fuzzy ranked, per result returned.
+const CANDIDATES: usize = 20;
+/// Most changes written to the index in one transaction.
+const BATCH_SIZE: usize = 500;
+/// Longest a change waits to be written while its batch fills up.
+const BATCH_TIME: Duration = Duration::from_millis(500);
+/// How recently a directory must have been modified to be indexed ahead of others.
+const RECENT: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Width and height of cached desktop entry icons.
+const ICON_SIZE: u32 = 64;
+/// Columns read into a [`Metadata`] by [`read_metadata`].
+const METADATA_COLUMNS: &str =
+    "files.kind, files.size, files.modified, files.changed, files.inode, files.device, files.owner, files.mime";
+
+pub mod desktop;
+pub mod frecency;
+pub mod fuzzy;
+pub mod icons;
+pub mod ignores;
+pub mod launch;
+pub mod query;
+pub mod schedule;
+pub mod schema;
+pub mod settings;
 pub mod ty;
-const MAX_DEPTH: usize = 5;
-/// If a file was indexed less than this many seconds ago, skip re-indexing.
-const REINDEX_THRESHOLD: i64 = 60;
-
-fn folders() -> Vec<PathBuf> {
-    let mut folders = vec![
-        Path::new("/usr/share/applications/").to_path_buf(),
-        Path::new("/usr/local/share/applications/").to_path_buf(),
-        Path::new(&var("HOME").unwrap()).to_path_buf(),
-    ];
-    if let Ok(path) = var("PATH") {
-        for folder in env::split_paths(&path) {
-            folders.push(folder);
-        }
-    }
-    folders
+mod watch;
+
+pub use watch::watch;
+
+/// What every directory of a walk shares.
+struct Walk {
+    settings: Arc<IndexConfig>,
+    ignore: Arc<Vec<Regex>>,
+    pool: Pool<SqliteConnectionManager>,
+    tx: mpsc::SyncSender<Change>,
+    /// Whether to descend into subdirectories which haven't changed since they were last indexed.
+    deep: bool,
+    /// Directories already walked, as those asked for may overlap.
+    visited: Mutex<HashSet<PathBuf>>,
+    /// Desktop entries' icons are looked up in, see [`icons::current_theme`].
+    icon_theme: String,
 }
 
-fn ignore() -> Vec<Regex> {
-    vec![
-        Regex::new("node_modules/.+").unwrap(),
-        Regex::new("target/.+").unwrap(),
-        Regex::new(r"/\..+").unwrap(),
-    ]
+/// A directory for a walk to index.
+struct Dir {
+    path: PathBuf,
+    depth: usize,
+    /// Of the root it's in.
+    max_depth: usize,
+    /// Of the root it's in.
+    priority: i32,
+    /// The rules in effect in it, including its own ignore files.
+    ignores: Ignores,
 }
 
-fn is_binary_file(path: &PathBuf) -> bool {
-    if let Some(ext) = path.extension() {
-        let ext = ext.to_string_lossy().to_lowercase();
-        return matches!(
-            ext.as_str(),
-            "exe" | "bin" | "o" | "dll" | "so" | "dat" | "class" | "rmeta" | "rlib" | "d"
-        );
+/// Indexes `dirs`, or every configured root if `None`, only writing entries which were
+/// added, changed (by their kind, size, inode or modification and status change times)
+/// or removed since they were last indexed. Every root also drops what's no longer in one.
+///
+/// Unlike [`refresh`], every subdirectory is descended into: a directory's times only change
+/// with its own entries, not ones nested further in, so they can't tell an unchanged subtree
+/// apart from one changed while nothing was watching it.
+pub fn index(dirs: Option<Vec<PathBuf>>, pool: Pool<SqliteConnectionManager>) {
+    if dirs.is_none() {
+        let pruned = pool.get().map_err(|e| e.to_string()).and_then(|mut conn| {
+            prune(&mut conn, &settings::current()).map_err(|e| e.to_string())
+        });
+        match pruned {
+            Ok(0) => {}
+            Ok(pruned) => info!("Removed {pruned} entries no longer within a root"),
+            Err(e) => error!("Failed to remove entries no longer within a root: {e}"),
+        }
     }
-    false
+    walk(dirs, true, pool)
 }
 
-pub fn index(dirs: Option<Vec<PathBuf>>, pool: Pool<SqliteConnectionManager>) {
-    let folders = dirs.unwrap_or(folders());
-    let ignore = Arc::new(ignore());
+/// Removes entries outside every folder of `settings`, or deeper than it goes, as left by
+/// roots being removed or made shallower. Returns how many were removed.
+pub fn prune(conn: &mut Connection, settings: &IndexConfig) -> rusqlite::Result<usize> {
+    // Entries of directories down to a folder's `max_depth` are indexed, so one level deeper
+    let folders = settings.folders();
+    let covered = |path: &Path| {
+        folders.iter().any(|f| path.strip_prefix(&f.path).is_ok_and(|within| within.components().count() <= f.max_depth + 1))
+    };
+
+    let tx = conn.transaction()?;
+    let stale = {
+        let mut stmt = tx.prepare("SELECT path FROM files")?;
+        let paths = stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+        paths.into_iter().filter(|path| !covered(Path::new(path))).collect::<Vec<_>>()
+    };
+    {
+        let mut remove = tx.prepare("DELETE FROM files WHERE path = ?1")?;
+        let mut remove_contents = tx.prepare("DELETE FROM contents WHERE path = ?1")?;
+        for path in &stale {
+            remove.execute([path])?;
+            remove_contents.execute([path])?;
+        }
+    }
+    tx.commit()?;
+    Ok(stale.len())
+}
+
+/// Indexes `dirs` like [`index`], but only descends into subdirectories which were added
+/// or changed since they were last indexed, for updating just what was seen to change.
+pub fn refresh(dirs: Vec<PathBuf>, pool: Pool<SqliteConnectionManager>) { walk(Some(dirs), false, pool) }
+
+fn walk(dirs: Option<Vec<PathBuf>>, deep: bool, pool: Pool<SqliteConnectionManager>) {
+    let settings = settings::current();
+    let ignore = Arc::new(settings.ignore_patterns());
+
+    // Directories within a root carry on from however deep they are in it, unless they're ignored
+    let folders = match dirs {
+        Some(dirs) => dirs
+            .into_iter()
+            .filter_map(|dir| {
+                let (folder, depth) = settings.folder_of(&dir)?;
+                let mut within = dir.ancestors().take_while(|a| *a != folder.path);
+                if within.any(|a| ignore.iter().any(|pat| pat.is_match(&a.to_string_lossy()))) {
+                    return None;
+                }
+                let ignores = Ignores::within(&settings, &folder.path, &dir)?;
+                Some(Dir { path: dir, depth, max_depth: folder.max_depth, priority: folder.priority, ignores })
+            })
+            .collect::<Vec<_>>(),
+        None => settings
+            .folders()
+            .into_iter()
+            .map(|folder| {
+                let ignores = Ignores::new(&settings).enter(&folder.path);
+                Dir { path: folder.path, depth: 0, max_depth: folder.max_depth, priority: folder.priority, ignores }
+            })
+            .collect(),
+    };
     // Use a sync_channel with a fixed capacity to prevent unbounded memory usage.
-    let (tx, rx) = mpsc::sync_channel::<ChannelData>(1000);
+    let (tx, rx) = mpsc::sync_channel::<Change>(1000);
 
-    // Spawn a thread to process file entries.
-    std::thread::spawn(move || {
+    std::thread::spawn({
         let pool = pool.clone();
-        while let Ok(data) = rx.recv() {
-            let conn = pool.get().unwrap();
-            println!("{:?}", &data.icon);
-            // Check if the file already exists and if it was indexed recently.
-            let should_index = match conn.query_row(
-                "SELECT last_accessed FROM files WHERE path = ?1",
-                params![&data.path_str],
-                |row| row.get::<_, i64>(0),
-            ) {
-                Ok(last_accessed) => {
-                    // Only re-index if the stored timestamp is older than our threshold.
-                    data.timestamp >= last_accessed + REINDEX_THRESHOLD
+        move || write(rx, pool, deep)
+    });
+
+    let icon_theme = icons::current_theme();
+    let walk = Arc::new(Walk { settings, ignore, pool, tx, deep, visited: Default::default(), icon_theme });
+    folders.into_iter().for_each(|dir| schedule(&walk, dir, false));
+}
+
+/// Entries a walk's writer has changed so far.
+#[derive(Default)]
+struct Tally {
+    added: usize,
+    updated: usize,
+    removed: usize,
+}
+
+/// Writes the changes a walk sends to the index until the walk ends, in transactions of up
+/// to [`BATCH_SIZE`] changes or [`BATCH_TIME`], logging whatever fails rather than giving up.
+fn write(rx: mpsc::Receiver<Change>, pool: Pool<SqliteConnectionManager>, deep: bool) {
+    let mut tally = Tally::default();
+    while let Ok(first) = rx.recv() {
+        let deadline = Instant::now() + BATCH_TIME;
+        let mut batch = vec![first];
+        while batch.len() < BATCH_SIZE {
+            match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(change) => batch.push(change),
+                Err(_) => break,
+            }
+        }
+
+        let count = batch.len();
+        let written = match pool.get() {
+            Ok(mut conn) => write_batch(&mut conn, batch, &mut tally).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match written {
+            Ok(()) => schedule::wrote(count as u64),
+            Err(e) => error!("Failed to write {count} changes to the index: {e}"),
+        }
+    }
+
+    let Tally { added, updated, removed } = tally;
+    match deep {
+        true => info!("Indexing done: {added} added, {updated} updated, {removed} removed"),
+        false => trace!("Refreshed: {added} added, {updated} updated, {removed} removed"),
+    }
+}
+
+/// Writes `batch` in one transaction. Changes which fail are logged and skipped,
+/// so only failing to commit loses the rest.
+fn write_batch(conn: &mut Connection, batch: Vec<Change>, tally: &mut Tally) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    {
+        // Upserting rather than REPLACE-ing, so the trigram index's update (not delete) trigger fires.
+        let mut upsert = tx.prepare_cached(
+            "INSERT INTO files (name, path, parent, depth, indexed_at, executable, desktop, icon,
+                kind, size, modified, changed, inode, device, owner, mime, entry, terms)
+             values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)
+             ON CONFLICT (path) DO UPDATE SET name = excluded.name, parent = excluded.parent, depth = excluded.depth,
+                indexed_at = excluded.indexed_at, executable = excluded.executable,
+                desktop = excluded.desktop, icon = excluded.icon, kind = excluded.kind, size = excluded.size,
+                modified = excluded.modified, changed = excluded.changed, inode = excluded.inode,
+                device = excluded.device, owner = excluded.owner, mime = excluded.mime,
+                entry = excluded.entry, terms = excluded.terms",
+        )?;
+        let mut remove = tx.prepare_cached("DELETE FROM files WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'")?;
+        let mut remove_contents = tx.prepare_cached("DELETE FROM contents WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'")?;
+        let mut clear_contents = tx.prepare_cached("DELETE FROM contents WHERE path = ?1")?;
+        let mut insert_contents = tx.prepare_cached("INSERT INTO contents (path, body) values (?1, ?2)")?;
+
+        for change in batch {
+            let (data, added) = match change {
+                Change::Added(data) => (data, true),
+                Change::Updated(data) => (data, false),
+                Change::Removed(path) => {
+                    let under = format!("{}/%", query::escape_like(&path));
+                    let removed = remove.execute(params![path, under]);
+                    match removed.and_then(|removed| remove_contents.execute(params![path, under]).map(|_| removed)) {
+                        Ok(removed) => tally.removed += removed,
+                        Err(e) => error!("Failed to remove {path} from the index: {e}"),
+                    }
+                    continue;
                 }
-                Err(_) => true, // File not indexed yet.
             };
 
-            if should_index {
-                // Using REPLACE here so that we update if it already exists.
-                conn.execute(
-                    "REPLACE INTO files (name, path, depth, last_accessed, executable, desktop, icon) values (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-                    params![data.name, data.path_str, data.depth, data.timestamp, data.executable, data.is_desktop, data.icon],
-                )
-                .unwrap();
+            let metadata = &data.metadata;
+            let entry = data.entry.as_ref().map(|e| serde_json::to_string(e).unwrap_or_default());
+            let terms = data.entry.as_ref().map(DesktopEntry::terms).unwrap_or_default();
+            let written = upsert
+                .execute(params![
+                    data.name,
+                    data.path_str,
+                    data.parent,
+                    data.depth,
+                    data.timestamp,
+                    data.executable,
+                    data.is_desktop,
+                    data.icon,
+                    metadata.kind.as_str(),
+                    metadata.size as i64,
+                    metadata.modified,
+                    metadata.changed,
+                    metadata.inode as i64,
+                    metadata.device as i64,
+                    metadata.owner,
+                    metadata.mime,
+                    entry,
+                    terms
+                ])
+                .and_then(|_| clear_contents.execute(params![data.path_str]))
+                .and_then(|_| match &data.contents {
+                    Some(contents) => insert_contents.execute(params![data.path_str, contents]),
+                    None => Ok(0),
+                });
+
+            match written {
+                Ok(_) if added => tally.added += 1,
+                Ok(_) => tally.updated += 1,
+                Err(e) => error!("Failed to index {}: {e}", data.path_str),
             }
         }
-    });
+    }
+    tx.commit()
+}
 
-    folders.into_iter().for_each(|f| {
-        std::thread::spawn({
-            let ignore = ignore.clone();
-            let tx = tx.clone();
-            move || task(f, ignore.clone(), tx.clone(), 0)
-        });
-    });
+/// Queues `dir` to be indexed, once those more pressing are done.
+fn schedule(walk: &Arc<Walk>, dir: Dir, recent: bool) {
+    if dir.depth > dir.max_depth {
+        return;
+    }
+
+    let rank = Rank { urgent: !walk.deep, priority: dir.priority, recent, depth: dir.depth };
+    let walk = walk.clone();
+    schedule::push(rank, move || task(&walk, dir));
 }
 
-fn task(dir: PathBuf, ignore: Arc<Vec<Regex>>, tx: mpsc::SyncSender<ChannelData>, depth: usize) {
-    if depth > MAX_DEPTH {
+/// Indexes the entries of `dir`, queueing its subdirectories.
+fn task(walk: &Arc<Walk>, dir: Dir) {
+    if !walk.visited.lock().unwrap().insert(dir.path.components().collect()) {
         return;
     }
 
-    trace!("Indexing {}", dir.display());
-    if let Ok(entries) = dir.read_dir() {
-        for entry in entries.filter_map(Result::ok) {
-            let path = entry.path();
-            let path_str = path.to_string_lossy().to_string();
-            let mut name = entry.file_name().to_string_lossy().to_string();
-            let mut icon = Option::<String>::None;
-            let mut is_desktop = false;
-
-            let ft = entry.file_type().unwrap();
-            if ft.is_dir() && !ignore.iter().any(|pat| pat.is_match(&path_str)) {
-                task(path, ignore.clone(), tx.clone(), depth + 1);
-            } else if ft.is_file() && !is_binary_file(&path) {
-                let timestamp = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs() as i64;
-                let executable = is_executable(&path);
-
-                if path
-                    .extension()
-                    .map(|v| v.to_string_lossy().to_string())
-                    .unwrap_or_default()
-                    == "desktop".to_string()
-                {
-                    let (new_name, new_icon) = read_desktop(&path).unwrap_or_default();
-                    name = if new_name.is_empty() { name } else { new_name };
-                    icon = new_icon;
-                    is_desktop = true;
-                }
+    trace!("Indexing {}", dir.path.display());
+    let Ok(entries) = dir.path.read_dir() else { return };
+    let settings = &walk.settings;
+    let recently = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().saturating_sub(RECENT).as_secs() as i64;
+
+    // Whatever was indexed here last time, less what's still here by the end
+    let mut vanished = match indexed_children(&walk.pool, &dir.path) {
+        Ok(children) => children,
+        Err(e) => return error!("Failed to read the index for {}: {e}", dir.path.display()),
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let path_str = path.to_string_lossy().to_string();
+
+        // Vanished since the directory was read, or unreadable
+        let Ok(ft) = entry.file_type() else { continue };
+        let indexed = if ft.is_dir() {
+            !walk.ignore.iter().any(|pat| pat.is_match(&path_str))
+        } else if ft.is_file() {
+            !settings.is_excluded(&path)
+        } else {
+            ft.is_symlink() && fs::read_link(&path).is_ok_and(|target| is_executable(&target))
+        } && !dir.ignores.is_ignored(&path, ft.is_dir());
+        if !indexed {
+            continue;
+        }
 
-                let _ = tx.send(ChannelData {
-                    name,
-                    path_str,
-                    timestamp,
-                    depth,
-                    executable,
-                    is_desktop,
-                    icon,
-                });
-            } else if ft.is_symlink() {
-                if let Ok(target) = fs::read_link(&path) {
-                    if is_executable(&target) {
-                        let timestamp = SystemTime::now()
-                            .duration_since(SystemTime::UNIX_EPOCH)
-                            .unwrap()
-                            .as_secs() as i64;
-                        let executable = true;
-                        // Send the symlink information. If the channel is full, this will block.
-                        let _ = tx.send(ChannelData {
-                            name,
-                            path_str,
-                            timestamp,
-                            depth,
-                            executable,
-                            is_desktop,
-                            icon,
-                        });
-                    }
-                }
-            }
+        let metadata = metadata(&path);
+        let recent = metadata.modified > recently;
+        let previous = vanished.remove(&path_str);
+        let changed = previous != Some(stamp(&metadata));
+        if changed {
+            let data = read_entry(&path, ft, dir.depth, metadata, walk);
+            // If the channel is full, this will block.
+            let _ = walk.tx.send(if previous.is_some() { Change::Updated(data) } else { Change::Added(data) });
+            std::thread::sleep(schedule::pause(settings));
+        }
 
-            std::thread::sleep(Duration::from_millis(100));
+        if ft.is_dir() && (walk.deep || changed) {
+            let ignores = dir.ignores.enter(&path);
+            let sub = Dir { path, depth: dir.depth + 1, max_depth: dir.max_depth, priority: dir.priority, ignores };
+            schedule(walk, sub, recent);
         }
     }
+
+    for path in vanished.into_keys() {
+        let _ = walk.tx.send(Change::Removed(path));
+    }
+}
+
+/// Stamps of every entry indexed directly within `dir`, by path.
+fn indexed_children(pool: &Pool<SqliteConnectionManager>, dir: &Path) -> Result<HashMap<String, Stamp>, String> {
+    let conn = pool.get().map_err(|e| e.to_string())?;
+    let mut stmt = conn
+        .prepare_cached("SELECT path, kind, modified, changed, size, inode FROM files WHERE parent = ?1")
+        .map_err(|e| e.to_string())?;
+
+    // Without any trailing `/`, like the parents stored
+    let dir = dir.components().collect::<PathBuf>();
+    let rows = stmt
+        .query_map(params![dir.to_string_lossy()], |row| {
+            let kind = row.get::<_, String>(1)?.parse().unwrap_or_default();
+            Ok((row.get(0)?, (kind, row.get(2)?, row.get(3)?, row.get::<_, i64>(4)? as u64, row.get::<_, i64>(5)? as u64)))
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Everything the index stores about an entry.
+fn read_entry(path: &Path, ft: FileType, depth: usize, metadata: Metadata, walk: &Walk) -> ChannelData {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let mut name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let mut icon = Option::<String>::None;
+    let mut entry = None;
+    let mut contents = None;
+
+    // Symlinks are only indexed if they point to an executable
+    let executable = ft.is_symlink() || ft.is_file() && is_executable(path);
+    if ft.is_file() {
+        contents = walk.settings.indexes_contents(path).then(|| read_contents(path, &walk.settings)).flatten();
+
+        if path.extension().is_some_and(|ext| ext == "desktop") {
+            entry = DesktopEntry::read(path);
+        }
+        if let Some(entry) = &entry {
+            name = if entry.name.is_empty() { name } else { entry.name.clone() };
+            icon = entry.icon.as_deref().and_then(|icon| cache_icon(&name, icon, &walk.icon_theme));
+        }
+    }
+
+    ChannelData {
+        name,
+        path_str: path.to_string_lossy().to_string(),
+        parent: path.parent().unwrap_or(path).to_string_lossy().to_string(),
+        timestamp,
+        depth,
+        metadata,
+        executable,
+        is_desktop: entry.is_some(),
+        icon,
+        entry,
+        contents,
+    }
+}
+
+/// Reads a file for content indexing, skipping large or non-UTF-8 (likely binary) ones.
+fn read_contents(path: &Path, settings: &IndexConfig) -> Option<String> {
+    if fs::metadata(path).ok()?.len() > settings.content_max_size {
+        return None;
+    }
+
+    fs::read_to_string(path).ok().filter(|c| !c.contains('\0'))
+}
+
+/// Metadata of `path` itself, without following symlinks.
+fn metadata(path: &Path) -> Metadata {
+    let Ok(m) = fs::symlink_metadata(path) else { return Metadata::default() };
+    let kind = match m.file_type() {
+        t if t.is_dir() => Kind::Dir,
+        t if t.is_symlink() => Kind::Symlink,
+        _ => Kind::File,
+    };
+
+    Metadata {
+        kind,
+        size: m.len(),
+        modified: m.mtime(),
+        changed: m.ctime(),
+        inode: m.ino(),
+        device: m.dev(),
+        owner: m.uid(),
+        mime: match kind {
+            Kind::File => mime_guess::from_path(path).first_raw().map(str::to_string),
+            Kind::Dir => Some("inode/directory".to_string()),
+            Kind::Symlink => Some("inode/symlink".to_string()),
+        },
+    }
+}
+
+/// Reads the [`METADATA_COLUMNS`] starting at column `from` of `row`.
+fn read_metadata(row: &Row, from: usize) -> rusqlite::Result<Metadata> {
+    Ok(Metadata {
+        kind: row.get::<_, String>(from)?.parse().unwrap_or_default(),
+        size: row.get::<_, i64>(from + 1)? as u64,
+        modified: row.get(from + 2)?,
+        changed: row.get(from + 3)?,
+        inode: row.get::<_, i64>(from + 4)? as u64,
+        device: row.get::<_, i64>(from + 5)? as u64,
+        owner: row.get(from + 6)?,
+        mime: row.get(from + 7)?,
+    })
+}
+
+/// The desktop entry stored in column `at` of `row`, if any.
+fn read_entry_column(row: &Row, at: usize) -> Option<Box<DesktopEntry>> {
+    row.get::<_, Option<String>>(at).ok().flatten().and_then(|entry| serde_json::from_str(&entry).ok())
+}
+
+/// Whether `entry`, found at `path`, shouldn't be shown, see [`DesktopEntry::is_shown`]
+/// and [`desktop::is_overridden`].
+fn is_hidden(entry: Option<&DesktopEntry>, path: &str) -> bool {
+    entry.is_some_and(|e| !e.is_shown() || desktop::is_overridden(Path::new(path)))
 }
 
-fn is_executable(path: &PathBuf) -> bool {
+fn is_executable(path: &Path) -> bool {
     if let Ok(metadata) = fs::metadata(path) {
         let permissions = metadata.permissions();
         permissions.mode() & 0o111 != 0
@@ -188,156 +507,363 @@ fn is_executable(path: &PathBuf) -> bool {
     }
 }
 
-fn read_desktop(path: &PathBuf) -> Result<(String, Option<String>), ()> {
-    let desktop = fs::read_to_string(&path).map_err(|_| ())?;
+/// Caches the desktop entry `name`'s `icon` from `theme` at [`ICON_SIZE`], returning where it's cached.
+fn cache_icon(name: &str, icon: &str, theme: &str) -> Option<String> {
+    let icon = icons::find(icon, theme, ICON_SIZE, 1)?;
+    let img = icons::load(&icon, ICON_SIZE)?;
 
-    let name = desktop
-        .lines()
-        .find(|l| l.starts_with("Name="))
-        .map(|n| n.split('=').skip(1).collect::<Vec<_>>().join("="))
-        .ok_or(())?;
+    let path = config::cache_dir().ok()?.join("icons").join(format!("{name}.png"));
+    fs::create_dir_all(path.parent()?).ok()?;
 
-    let icon = desktop.lines().find(|l| l.starts_with("Icon="));
-    let icon = icon.map(|i| i.split('=').last().unwrap_or_default().to_string());
+    img.save(&path).ok()?;
+    Some(path.to_string_lossy().to_string())
+}
 
-    if let Some(icon) = icon.and_then(|icon| resolve_icon(&icon)) {
-        if let Ok(img) = ImageReader::open(Path::new(&icon)).unwrap().decode() {
-            let img = img.resize(64, 64, image::imageops::FilterType::Lanczos3);
+/// Points icons cached under `from` at the same files under `to`, for when the cache was moved,
+/// like out of [`config::paths::legacy_dir`]. Returns how many were moved.
+pub fn relocate_icons(conn: &Connection, from: &Path, to: &Path) -> rusqlite::Result<usize> {
+    let (from, to) = (format!("{}/", from.display()), format!("{}/", to.display()));
+    conn.execute(
+        "UPDATE files SET icon = ?2 || substr(icon, length(?1) + 1) WHERE desktop AND substr(icon, 1, length(?1)) = ?1",
+        params![from, to],
+    )
+}
 
-            let path = config::finick_root()
-                .join("icons")
-                .join(format!("{name}.png"));
-            fs::create_dir_all(path.parent().unwrap()).unwrap();
+/// Why a search failed, sent back to the client as is.
+#[derive(Debug)]
+pub enum SearchError {
+    Query(ParseError),
+    Pool(r2d2::Error),
+    Database(rusqlite::Error),
+}
 
-            img.save(&path).map_err(|_| ())?;
-            return Ok((name, Some(path.to_string_lossy().to_string())));
+impl Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::Query(e) => write!(f, "{e}"),
+            SearchError::Pool(e) => write!(f, "Failed to connect to the index: {e}"),
+            SearchError::Database(e) => write!(f, "Failed to search the index: {e}"),
         }
     }
+}
 
-    Ok((name, None))
-}
-
-fn resolve_icon(name: &str) -> Option<String> {
-    let locations = vec![
-        env::var("HOME").map(|v| format!("{v}/.icons/")).ok(),
-        env::var("XDG_DATA_HOME")
-            .map(|v| format!("{v}/icons/"))
-            .ok(),
-        Some("/usr/share/pixmaps".to_string()),
-    ]
-    .into_iter()
-    .filter_map(|v| v);
-
-    for location in locations {
-        if let Ok(dir) = read_dir(location) {
-            if let Some(icon) = dir
-                .filter_map(|f| f.ok())
-                .find(|f| f.file_name().to_string_lossy().starts_with(name))
-            {
-                return Some(icon.path().to_string_lossy().to_string());
-            }
-        }
-    }
+impl std::error::Error for SearchError {}
 
-    None
+impl From<ParseError> for SearchError {
+    fn from(e: ParseError) -> Self { SearchError::Query(e) }
 }
 
-pub fn search(query: &str, pool: Pool<SqliteConnectionManager>, cb: impl Fn(SearchResult)) {
-    let like_query = format!("%{query}%");
-    let conn = pool.get().unwrap();
+impl From<r2d2::Error> for SearchError {
+    fn from(e: r2d2::Error) -> Self { SearchError::Pool(e) }
+}
 
-    // First, search in the database
-    let mut res = conn
-        .prepare(
-            "SELECT name, path, icon, desktop, executable
-             FROM files
-             WHERE name LIKE ?1 OR path LIKE ?1
-             ORDER BY executable DESC, last_accessed DESC, depth ASC, LENGTH(replace(name, ?1, '')) ASC
-             LIMIT 100",
-        )
-        .unwrap();
+impl From<rusqlite::Error> for SearchError {
+    fn from(e: rusqlite::Error) -> Self { SearchError::Database(e) }
+}
 
+/// Searches for files matching `req`, see [`query`] for the syntax, calling `cb` with each.
+pub fn search(
+    query: &str,
+    scope: Scope,
+    pool: Pool<SqliteConnectionManager>,
+    cb: impl Fn(SearchResult),
+) -> Result<(), SearchError> {
+    let query = Query::parse(query)?;
+    let conn = pool.get()?;
     let mut found_paths = std::collections::HashSet::new();
 
-    let mut rows = res.query(params![like_query]).unwrap();
-    while let Some(row) = rows.next().unwrap() {
-        let name: String = row.get(0).unwrap();
-        let path: String = row.get(1).unwrap();
-        let icon: Option<String> = row.get(2).unwrap();
-        let desktop: bool = row.get(3).unwrap();
-        let executable: bool = row.get(4).unwrap();
-
-        found_paths.insert(path.clone());
-        cb(SearchResult {
-            name,
-            path,
-            icon,
-            is_desktop: desktop,
-            is_executable: executable,
-        });
+    if scope != Scope::Contents {
+        search_names(&query, &conn, &mut found_paths, &cb)?;
     }
 
-    // Quick 1-depth search in $PATH directories
-    if let Ok(path_var) = var("PATH") {
-        for folder in env::split_paths(&path_var) {
-            if let Ok(entries) = folder.read_dir() {
-                for entry in entries.filter_map(Result::ok) {
-                    let path = entry.path();
-                    let name = entry.file_name().to_string_lossy().to_string();
-
-                    if path.is_file()
-                        && is_executable(&path)
-                        && path
-                            .file_name()
-                            .map(|v| v.to_string_lossy())
-                            .unwrap_or_default()
-                            .to_string()
-                            .contains(query)
-                    {
-                        let path_str = path.to_string_lossy().to_string();
-                        if !found_paths.contains(&path_str) {
-                            cb(SearchResult {
-                                name,
-                                path: path.to_string_lossy().to_string(),
-                                is_desktop: false,
-                                is_executable: true,
-                                icon: None,
-                            });
-                        }
-                    }
-                }
+    if scope != Scope::Names {
+        search_contents(&query, &conn, &mut found_paths, &cb)?;
+    }
+
+    Ok(())
+}
+
+fn search_names(
+    query: &Query,
+    conn: &Connection,
+    found_paths: &mut std::collections::HashSet<String>,
+    cb: &impl Fn(SearchResult),
+) -> rusqlite::Result<()> {
+    let limit = settings::current().max_results;
+
+    // Bare words are matched fuzzily by `rank`, everything else in SQL
+    let words = query.words().collect::<Vec<_>>();
+    let (condition, condition_params) = query.condition(|word| matches!(word, Filter::Text(_)));
+
+    // Narrowing things down by the longest word helps the most
+    let prefilters = match words.iter().filter_map(|w| w.text()).max_by_key(|w| w.chars().count()) {
+        Some(needle) => prefilters(needle),
+        None => vec![Prefilter::All],
+    };
+
+    let order = query.sort.map(|s| format!("{}, ", s.sql())).unwrap_or_default();
+    let mut ranked = Vec::new();
+
+    // $PATH is searched alongside, for plain queries as filters can't apply there
+    let programs = match query.is_plain() && !words.is_empty() {
+        true => programs(),
+        false => vec![],
+    };
+
+    for prefilter in prefilters {
+        let (source, params) = prefilter.sql();
+        let mut res = conn.prepare(&format!(
+            "SELECT files.name, files.path, files.icon, files.desktop, files.executable, files.indexed_at, files.depth,
+                files.terms, files.entry, {METADATA_COLUMNS}
+             FROM {source} AND ({condition})
+             ORDER BY {order}files.path IN (SELECT path FROM opens) DESC, files.depth ASC
+             LIMIT {}",
+            limit * CANDIDATES
+        ))?;
+
+        let params = params.into_iter().map(Value::Text).chain(condition_params.iter().cloned());
+        let mut rows = res.query(rusqlite::params_from_iter(params))?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let path: String = row.get(1)?;
+            let terms: String = row.get(7)?;
+            let Some(score) = rank(&words, &name, &path, &terms) else { continue };
+
+            // Desktop entries which are hidden, overridden or not for this desktop aren't results
+            let entry = read_entry_column(row, 8);
+            if is_hidden(entry.as_deref(), &path) {
+                continue;
             }
+
+            let indexed_at: i64 = row.get(5)?;
+            let depth: usize = row.get(6)?;
+            let metadata = read_metadata(row, 9)?;
+            let result = SearchResult {
+                name,
+                path,
+                is_dir: metadata.kind == Kind::Dir,
+                icon: row.get(2)?,
+                is_desktop: row.get(3)?,
+                is_executable: row.get(4)?,
+                snippet: None,
+                score,
+                metadata,
+                entry,
+            };
+            ranked.push((result, indexed_at, depth));
         }
+
+        // Ranked by name, as `/usr/bin` says nothing about them
+        let found = ranked.iter().map(|(r, ..)| r.path.clone()).collect::<HashSet<_>>();
+        for (name, path) in programs.iter().filter(|(name, _)| prefilter.matches(name)) {
+            let Some(score) = rank(&words, name, name, "") else { continue };
+            let path_str = path.to_string_lossy().to_string();
+            if found.contains(&path_str) || !path.is_file() || !is_executable(path) {
+                continue;
+            }
+
+            let result = SearchResult {
+                name: name.clone(),
+                path: path_str,
+                is_dir: false,
+                is_desktop: false,
+                is_executable: true,
+                icon: None,
+                snippet: None,
+                score,
+                metadata: metadata(path),
+                entry: None,
+            };
+            ranked.push((result, 0, 0));
+        }
+
+        if !ranked.is_empty() {
+            break;
+        }
+    }
+
+    // Blending in how often and recently each was opened
+    match frecency::all(conn) {
+        Ok(opens) => ranked.iter_mut().for_each(|(result, ..)| {
+            result.score += opens.get(&result.path).map_or(0, |f| frecency::bonus(*f));
+        }),
+        Err(e) => error!("Failed to read how often files were opened: {e}"),
     }
+
+    ranked.sort_by(|(a, a_indexed, a_depth), (b, b_indexed, b_depth)| {
+        let sorted = query.sort.map(|s| s.compare(a, b)).unwrap_or(Ordering::Equal);
+        sorted
+            .then(b.score.cmp(&a.score))
+            .then(b.is_executable.cmp(&a.is_executable))
+            .then(b_indexed.cmp(a_indexed))
+            .then(a_depth.cmp(b_depth))
+    });
+
+    for (result, ..) in ranked.into_iter().take(limit) {
+        found_paths.insert(result.path.clone());
+        cb(result);
+    }
+    Ok(())
 }
 
-pub fn watch(pool: Pool<SqliteConnectionManager>) {
-    let (tx, rx) = mpsc::channel(); // std::sync::mpsc::channel
+/// Every file directly in a `$PATH` directory, by name.
+fn programs() -> Vec<(String, PathBuf)> {
+    let Some(path) = var("PATH").ok() else { return vec![] };
+    let entries = env::split_paths(&path).filter_map(|folder| folder.read_dir().ok()).flatten().filter_map(Result::ok);
+    entries.map(|entry| (entry.file_name().to_string_lossy().to_string(), entry.path())).collect()
+}
 
-    let mut watcher: RecommendedWatcher = Watcher::new(tx, Config::default()).unwrap();
+/// Scores a file against every bare word and phrase, or `None` if any doesn't match.
+/// Words may match one of its desktop entry's `terms` instead, as they would its path,
+/// but not letters picked from several of them.
+fn rank(words: &[&Filter], name: &str, path: &str, terms: &str) -> Option<i64> {
+    let contains = |haystack: &str, phrase: &str| haystack.to_lowercase().contains(&phrase.to_lowercase());
+    let term = |text: &str| terms.split_whitespace().filter_map(|term| fuzzy::score(text, term)).max();
+    words
+        .iter()
+        .map(|word| match word {
+            Filter::Phrase(phrase) if !contains(path, phrase) && !contains(terms, phrase) => None,
+            // Phrases were checked to be in one or the other as-is, so may span terms
+            Filter::Phrase(text) => fuzzy::rank(text, name, path).max(fuzzy::score(text, terms)),
+            Filter::Text(text) => fuzzy::rank(text, name, path).max(term(text)),
+            _ => Some(0),
+        })
+        .sum()
+}
+
+/// A way of narrowing the index down to files worth fuzzy ranking, see [`prefilters`].
+enum Prefilter {
+    /// Everything, for queries without a word to go by.
+    All,
+    /// Paths (or desktop entries' terms) containing the needle.
+    Contains(String),
+    /// Paths containing any one-typo variant of the needle.
+    Typos(Vec<String>),
+    /// Paths containing the needle as a subsequence, e.g. `ffx` for `firefox`.
+    Subsequence(String),
+}
 
-    for folder in folders() {
-        let _ = watcher.watch(&folder, RecursiveMode::Recursive);
+impl Prefilter {
+    /// A `FROM ... WHERE` clause for the files which pass, and its parameters.
+    fn sql(&self) -> (String, Vec<String>) {
+        let trigrams = "names JOIN files ON files.rowid = names.rowid WHERE names MATCH ?".to_string();
+        let like = "files WHERE (files.path LIKE ?1 ESCAPE '\\' OR files.terms LIKE ?1 ESCAPE '\\')".to_string();
+        match self {
+            Prefilter::All => ("files WHERE 1".to_string(), vec![]),
+            // Trigrams need three characters, so shorter needles fall back to `LIKE`
+            Prefilter::Contains(needle) if needle.chars().count() < 3 => {
+                (like, vec![format!("%{}%", query::escape_like(needle))])
+            }
+            Prefilter::Contains(needle) => (trigrams, vec![quote(needle)]),
+            Prefilter::Typos(typos) => (trigrams, vec![typos.iter().map(|t| quote(t)).collect::<Vec<_>>().join(" OR ")]),
+            Prefilter::Subsequence(needle) => (like, vec![fuzzy::like_pattern(needle)]),
+        }
     }
 
-    std::thread::spawn(move || loop {
-        match rx.recv() {
-            Ok(Ok(event)) => match event.kind {
-                EventKind::Create(_) => {
-                    index(Some(event.paths), pool.clone());
-                }
-                EventKind::Remove(_) => {
-                    event.paths.iter().for_each(|p| {
-                        let param = format!("{}%", p.display());
-                        pool.get()
-                            .unwrap()
-                            .execute("DELETE FROM files WHERE path LIKE ?1", params![param])
-                            .unwrap();
-                    });
-                }
-                _ => {}
-            },
-            _ => {}
+    /// Whether `text` passes, the same as it would in SQL.
+    fn matches(&self, text: &str) -> bool {
+        let contains = |needle: &str| text.to_lowercase().contains(&needle.to_lowercase());
+        match self {
+            Prefilter::All => true,
+            Prefilter::Contains(needle) => contains(needle),
+            Prefilter::Typos(typos) => typos.iter().any(|t| contains(t)),
+            Prefilter::Subsequence(needle) => fuzzy::score(needle, text).is_some(),
         }
+    }
+}
+
+/// Ways of narrowing the index down by `needle`, to be tried in order until one finds
+/// something: containing it, then a one-typo variant of it, both served by the trigram
+/// index, and finally containing it as a subsequence. That last one is a full scan, so
+/// it's only a fallback.
+fn prefilters(needle: &str) -> Vec<Prefilter> {
+    let mut prefilters = vec![Prefilter::Contains(needle.to_string())];
+
+    let typos = fuzzy::typo_variants(needle).collect::<Vec<_>>();
+    if !typos.is_empty() {
+        prefilters.push(Prefilter::Typos(typos));
+    }
+
+    prefilters.push(Prefilter::Subsequence(needle.to_string()));
+    prefilters
+}
+
+/// Quotes `term` as an FTS5 string, so any syntax in it is matched literally.
+fn quote(term: &str) -> String { format!("\"{}\"", term.replace('"', "\"\"")) }
+
+fn search_contents(
+    query: &Query,
+    conn: &Connection,
+    found_paths: &mut std::collections::HashSet<String>,
+    cb: &impl Fn(SearchResult),
+) -> rusqlite::Result<()> {
+    // Bare words and phrases are searched for in contents, everything else filters the files
+    let terms = query.words().filter_map(Filter::text).map(str::to_lowercase).collect::<Vec<_>>();
+    if terms.is_empty() {
+        return Ok(());
+    }
+
+    let fts_query = terms.iter().map(|t| quote(t)).collect::<Vec<_>>().join(" ");
+    let (condition, condition_params) = query.condition(|_| true);
+    let order = query.sort.map(|s| s.sql()).unwrap_or("rank".to_string());
+    let limit = settings::current().max_results;
+
+    let mut res = conn.prepare(&format!(
+        "SELECT files.name, files.path, files.icon, files.desktop, files.executable, contents.body, files.entry,
+            {METADATA_COLUMNS}
+         FROM contents JOIN files ON files.path = contents.path
+         WHERE contents MATCH ? AND ({condition})
+         ORDER BY {order}
+         LIMIT {limit}"
+    ))?;
+
+    let opens = frecency::all(conn).unwrap_or_else(|e| {
+        error!("Failed to read how often files were opened: {e}");
+        HashMap::new()
     });
+
+    let params = std::iter::once(Value::Text(fts_query)).chain(condition_params);
+    let mut rows = res.query(rusqlite::params_from_iter(params))?;
+    let mut results = vec![];
+    while let Some(row) = rows.next()? {
+        let path: String = row.get(1)?;
+        if !found_paths.insert(path.clone()) {
+            continue;
+        }
+
+        let entry = read_entry_column(row, 6);
+        if is_hidden(entry.as_deref(), &path) {
+            continue;
+        }
+
+        let body: String = row.get(5)?;
+        results.push(SearchResult {
+            name: row.get(0)?,
+            score: opens.get(&path).map_or(0, |f| frecency::bonus(*f)),
+            path,
+            is_dir: false,
+            icon: row.get(2)?,
+            is_desktop: row.get(3)?,
+            is_executable: row.get(4)?,
+            snippet: snippet(&body, &terms),
+            metadata: read_metadata(row, 7)?,
+            entry,
+        });
+    }
+
+    // Those opened more come first, otherwise keeping to the order asked for or how well they match
+    results.sort_by(|a, b| query.sort.map(|s| s.compare(a, b)).unwrap_or(Ordering::Equal).then(b.score.cmp(&a.score)));
+    results.into_iter().for_each(cb);
+    Ok(())
+}
+
+/// The first line containing any of the (lowercased) terms.
+fn snippet(body: &str, terms: &[String]) -> Option<Snippet> {
+    body.lines().enumerate().find_map(|(i, line)| {
+        let lower = line.to_lowercase();
+        terms.iter().any(|t| lower.contains(t)).then(|| Snippet {
+            line: i + 1,
+            text: line.trim().chars().take(SNIPPET_LENGTH).collect(),
+        })
+    })
 }
@@ -1,35 +1,74 @@
-use std::{sync::mpsc::Sender, thread};
+use std::{sync::mpsc::{self, Sender}, thread};
 
 use config::ty::App;
-use index::ty::{Request, SearchResult};
+use index::{
+    settings::{self, IndexConfig},
+    ty::{Request, Response},
+};
+use ipsea::log::{debug, error, info};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::params;
 
 #[tokio::main]
 async fn main() {
     pretty_env_logger::init();
-    let manager = SqliteConnectionManager::file(config::finick_root().join("index.db"));
+    settings::register_migrations();
+    match config::get_config::<IndexConfig>(App::IndexService) {
+        Ok(config) => settings::set(config),
+        Err(e) => error!("Invalid config, using defaults:\n{e}"),
+    }
+    let manager = SqliteConnectionManager::file(config::data_dir().expect("No data dir").join("index.db"));
     let pool = Pool::new(manager).unwrap();
     pool.get().unwrap().execute_batch("PRAGMA journal_mode = WAL;").unwrap();
  
-    pool.get().unwrap().execute("CREATE TABLE IF NOT EXISTS files (
-        name TEXT NOT NULL,
-        path TEXT PRIMARY KEY,
-        depth INTEGER NOT NULL,
-        executable BOOL NOT NULL,
-        desktop BOOL NOT NULL,
-        icon TEXT,
-        last_accessed INTEGER NOT NULL
-    )", params![]).unwrap();
+    if let Err(e) = index::schema::migrate(&mut pool.get().unwrap()) {
+        error!("{e}");
+        std::process::exit(1);
+    }
 
-    thread::spawn({ let pool = pool.clone(); move || index::watch(pool.clone())});
+    // Icons cached before the move to the XDG directories are still pointed at where they were
+    if let (Ok(legacy), Ok(cache)) = (config::paths::legacy_dir(), config::cache_dir()) {
+        match index::relocate_icons(&pool.get().unwrap(), &legacy.join("icons"), &cache.join("icons")) {
+            Ok(0) => {}
+            Ok(moved) => info!("Pointed {moved} icons at {}", cache.join("icons").display()),
+            Err(e) => error!("Failed to update cached icon paths: {e}"),
+        }
+    }
+
+    let (roots_changed, rewatch) = mpsc::channel();
+    thread::spawn({ let pool = pool.clone(); move || index::watch(pool.clone(), rewatch)});
     thread::spawn({ let pool = pool.clone(); move || index::index(None, pool.clone())});
+    thread::spawn({
+        let pool = pool.clone();
+        move || match config::watch::<IndexConfig>(App::IndexService) {
+            Ok(reloads) => reloads.for_each(|config| {
+                info!("Config reloaded, reindexing");
+                settings::set(config);
+                let _ = roots_changed.send(());
+                index::index(None, pool.clone());
+            }),
+            Err(e) => error!("Failed to watch config: {e}"),
+        }
+    });
     ipsea::start_server(App::IndexService, {
         let pool = pool.clone();
-        move |t: Request, sender: Sender<SearchResult>| {
-            println!("Searching for {}", &t.query);
-            if t.query.len() > 2 { index::search(&t.query, pool.clone(), |v| { let _ = sender.send(v); }) };
+        move |t: Request, sender: Sender<Response>| match t {
+            Request::Search { query, scope } => {
+                debug!("Searching for {query}");
+                if query.len() > 2 {
+                    if let Err(e) = index::search(&query, scope, pool.clone(), |v| { let _ = sender.send(Response::Result(v)); }) {
+                        let _ = sender.send(Response::Error(e.to_string()));
+                    }
+                };
+            }
+            Request::Progress => { let _ = sender.send(Response::Progress(index::schedule::progress())); }
+            Request::Opened { path } => match pool.get() {
+                Ok(conn) => match index::frecency::record(&conn, &path) {
+                    Ok(()) => info!("Opened {path}"),
+                    Err(e) => error!("Failed to record {path} being opened: {e}"),
+                },
+                Err(e) => error!("Failed to record {path} being opened: {e}"),
+            },
         }
     }).expect("Failed to start index service");
 }
\ No newline at end of file
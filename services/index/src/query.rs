@@ -0,0 +1,530 @@
+//! The search query language.
+//!
+//! A query is made of whitespace separated terms, all of which must match:
+//!
+//! - `word` fuzzily matches the file's name or path, see [`crate::fuzzy`]
+//! - `"some phrase"` matches names or paths containing it as-is
+//! - `ext:rs`, or `ext:rs|toml` for either, matches by extension
+//! - `type:dir`, `type:file`, `type:symlink`, `type:app` (desktop entries) or `type:exec` (executables)
+//! - `mime:image/png`, or `mime:image` for any image, matches by MIME type (guessed from the extension)
+//! - `owner:root` or `owner:1000` matches by owner
+//! - `in:~/projects` matches files under a directory, or `in:target` under any directory named so
+//! - `size:>10M` compares sizes, with `<`, `<=`, `>`, `>=` or `=` and an optional `K`, `M`, `G` or `T`
+//! - `modified:<7d` compares how long ago files were modified, in `s`, `m` (minutes), `h`, `d`, `w` or `y`,
+//!   and `changed:<7d` likewise for their status (e.g. permissions) changing
+//!
+//! `sort:name`, `sort:path`, `sort:size`, `sort:modified` or `sort:changed` orders results
+//! by that rather than how well they match, with `-asc` or `-desc` to pick a direction.
+//!
+//! Any term can be negated with a leading `-`, and terms joined by `OR` match if
+//! either does. `OR` binds tighter than the implicit `AND` between terms, so
+//! `main ext:rs OR ext:toml` finds `main` with either extension.
+
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::types::Value;
+
+use crate::{settings, ty::SearchResult};
+
+/// Filters the query language knows, to tell typos of them from text with a colon in it.
+const KEYS: [&str; 9] = ["ext", "type", "mime", "owner", "in", "size", "modified", "changed", "sort"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    /// Every clause must match, and a clause matches if any of its terms do.
+    pub clauses: Vec<Vec<Term>>,
+    pub sort: Option<Sort>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub negated: bool,
+    pub filter: Filter,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// A bare word, matched fuzzily where possible.
+    Text(String),
+    /// A quoted phrase, matched exactly.
+    Phrase(String),
+    Ext(Vec<String>),
+    Type(Vec<Type>),
+    Mime(Vec<String>),
+    /// User IDs, with names resolved when parsing.
+    Owner(Vec<u32>),
+    In(String),
+    Size(Comparison, u64),
+    /// Compares the time since modification, in seconds.
+    Modified(Comparison, i64),
+    /// Compares the time since the status last changed, in seconds.
+    Changed(Comparison, i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Dir,
+    File,
+    Symlink,
+    App,
+    Exec,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Path,
+    Size,
+    Modified,
+    Changed,
+}
+
+impl Sort {
+    /// An `ORDER BY` term on the `files` table.
+    pub fn sql(&self) -> String {
+        let column = match self.key {
+            SortKey::Name => "files.name COLLATE NOCASE",
+            SortKey::Path => "files.path COLLATE NOCASE",
+            SortKey::Size => "files.size",
+            SortKey::Modified => "files.modified",
+            SortKey::Changed => "files.changed",
+        };
+        format!("{column} {}", if self.descending { "DESC" } else { "ASC" })
+    }
+
+    /// Orders results the same way as [`Sort::sql`].
+    pub fn compare(&self, a: &SearchResult, b: &SearchResult) -> Ordering {
+        let ordering = match self.key {
+            SortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SortKey::Path => a.path.to_lowercase().cmp(&b.path.to_lowercase()),
+            SortKey::Size => a.metadata.size.cmp(&b.metadata.size),
+            SortKey::Modified => a.metadata.modified.cmp(&b.metadata.modified),
+            SortKey::Changed => a.metadata.changed.cmp(&b.metadata.changed),
+        };
+
+        match self.descending {
+            true => ordering.reverse(),
+            false => ordering,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+}
+
+impl Filter {
+    /// What a bare word or phrase matches.
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Filter::Text(text) | Filter::Phrase(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+impl Comparison {
+    fn sql(self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Equal => "=",
+        }
+    }
+
+    /// The comparison with its sides swapped, e.g. for ages compared as timestamps.
+    fn flipped(self) -> Self {
+        match self {
+            Comparison::Less => Comparison::Greater,
+            Comparison::LessOrEqual => Comparison::GreaterOrEqual,
+            Comparison::Greater => Comparison::Less,
+            Comparison::GreaterOrEqual => Comparison::LessOrEqual,
+            Comparison::Equal => Comparison::Equal,
+        }
+    }
+}
+
+/// A query which couldn't be parsed, sent back to the client as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError(pub String);
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "Invalid query: {}", self.0) }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A whitespace separated token, with quotes removed.
+struct Token {
+    text: String,
+    /// Where in `text` the first quoted part started, if any.
+    quoted_from: Option<usize>,
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = query.chars().peekable();
+
+    while chars.peek().is_some() {
+        if chars.next_if(|c| c.is_whitespace()).is_some() {
+            continue;
+        }
+
+        let mut token = Token { text: String::new(), quoted_from: None };
+        let mut quoted = false;
+        while let Some(c) = chars.next_if(|c| quoted || !c.is_whitespace()) {
+            if c == '"' {
+                quoted = !quoted;
+                token.quoted_from.get_or_insert(token.text.len());
+            } else {
+                token.text.push(c);
+            }
+        }
+
+        if quoted {
+            return Err(ParseError(format!("unterminated quote in `{}`", token.text)));
+        }
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Self, ParseError> {
+        let mut clauses: Vec<Vec<Term>> = vec![];
+        let mut sort = None;
+        let mut joining = false;
+        let mut sorted = false;
+
+        for token in tokenize(query)? {
+            if token.text == "OR" && token.quoted_from.is_none() {
+                if joining || clauses.is_empty() || sorted {
+                    return Err(ParseError("`OR` needs a term on either side".to_string()));
+                }
+                joining = true;
+                continue;
+            }
+
+            sorted = false;
+            if let Some(key) = token.text.strip_prefix("sort:").filter(|_| token.quoted_from.is_none()) {
+                if joining || sort.is_some() {
+                    return Err(ParseError("`sort:` can only be given once, and not as part of an `OR`".to_string()));
+                }
+                sort = Some(parse_sort(key)?);
+                sorted = true;
+                continue;
+            }
+
+            let term = parse_term(token)?;
+            match clauses.last_mut() {
+                Some(clause) if joining => clause.push(term),
+                _ => clauses.push(vec![term]),
+            }
+            joining = false;
+        }
+
+        if joining {
+            return Err(ParseError("`OR` needs a term on either side".to_string()));
+        }
+        Ok(Query { clauses, sort })
+    }
+
+    /// Bare words and phrases every match has to contain, i.e. those not negated or part of an `OR`.
+    pub fn words(&self) -> impl Iterator<Item = &Filter> {
+        self.clauses.iter().filter_map(|clause| match clause.as_slice() {
+            [Term { negated: false, filter: filter @ (Filter::Text(_) | Filter::Phrase(_)) }] => Some(filter),
+            _ => None,
+        })
+    }
+
+    /// Whether the query is only bare words and phrases, without any filters.
+    pub fn is_plain(&self) -> bool { self.words().count() == self.clauses.len() }
+
+    /// Compiles the query into an SQL condition on the `files` table, with its
+    /// parameters, leaving out any of [`Query::words`] for which `skip` is true.
+    pub fn condition(&self, skip: impl Fn(&Filter) -> bool) -> (String, Vec<Value>) {
+        let mut params = vec![];
+        let clauses = self
+            .clauses
+            .iter()
+            .filter(|clause| match clause.as_slice() {
+                [Term { negated: false, filter: filter @ (Filter::Text(_) | Filter::Phrase(_)) }] => !skip(filter),
+                _ => true,
+            })
+            .map(|clause| {
+                let terms = clause.iter().map(|t| t.sql(&mut params)).collect::<Vec<_>>();
+                format!("({})", terms.join(" OR "))
+            })
+            .collect::<Vec<_>>();
+
+        match clauses.is_empty() {
+            true => ("1".to_string(), params),
+            false => (clauses.join(" AND "), params),
+        }
+    }
+}
+
+fn parse_term(token: Token) -> Result<Term, ParseError> {
+    let (negated, text, quoted_from) = match token.text.strip_prefix('-') {
+        // A quoted leading `-` is part of a phrase
+        Some(rest) if token.quoted_from != Some(0) => {
+            if rest.is_empty() && token.quoted_from.is_none() {
+                return Err(ParseError("`-` needs a term to negate".to_string()));
+            }
+            (true, rest.to_string(), token.quoted_from.map(|q| q - 1))
+        }
+        _ => (false, token.text, token.quoted_from),
+    };
+
+    if quoted_from == Some(0) {
+        if text.is_empty() {
+            return Err(ParseError("empty quotes".to_string()));
+        }
+        return Ok(Term { negated, filter: Filter::Phrase(text) });
+    }
+
+    // Only keys before any quoted part count, so `in:"My Projects"` works but `x"a:b"` is just text
+    let key = text.split_once(':').filter(|(key, _)| quoted_from.is_none_or(|q| key.len() < q));
+    let Some((key, value)) = key else { return Ok(Term { negated, filter: Filter::Text(text) }) };
+
+    let filter = match key {
+        "ext" => Filter::Ext(alternatives(key, value)?.map(|e| e.trim_start_matches('.').to_lowercase()).collect()),
+        "type" => Filter::Type(alternatives(key, value)?.map(parse_type).collect::<Result<_, _>>()?),
+        "mime" => Filter::Mime(alternatives(key, value)?.map(str::to_lowercase).collect()),
+        "owner" => Filter::Owner(alternatives(key, value)?.map(parse_owner).collect::<Result<_, _>>()?),
+        "in" if value.is_empty() => return Err(ParseError("`in:` needs a directory".to_string())),
+        "in" => Filter::In(value.to_string()),
+        "size" => {
+            let (comparison, size) = comparison(key, value)?;
+            Filter::Size(comparison, parse_size(size)?)
+        }
+        "modified" => {
+            let (comparison, age) = comparison(key, value)?;
+            Filter::Modified(comparison, parse_age(age)?)
+        }
+        "changed" => {
+            let (comparison, age) = comparison(key, value)?;
+            Filter::Changed(comparison, parse_age(age)?)
+        }
+        "sort" => return Err(ParseError("`sort:` can't be negated".to_string())),
+        // Other keys are likely part of what's searched for, e.g. `12:30` or `std::fs`, unless they're a typo of one
+        _ => match KEYS.into_iter().find(|known| is_typo(key, known)) {
+            Some(known) => return Err(ParseError(format!("unknown filter `{key}:`, did you mean `{known}:`?"))),
+            None => Filter::Text(text.clone()),
+        },
+    };
+
+    Ok(Term { negated, filter })
+}
+
+/// Whether `key` is `known` in another case, or one letter added, dropped or changed from it.
+/// Short keys like `in` only count in another case, as a letter off them is likely a word.
+fn is_typo(key: &str, known: &str) -> bool {
+    let (key, known) = (key.to_lowercase().chars().collect::<Vec<_>>(), known.chars().collect::<Vec<_>>());
+    if key == known {
+        return true;
+    }
+    if known.len() < 3 || key.len().abs_diff(known.len()) > 1 || !key.iter().all(char::is_ascii_alphabetic) {
+        return false;
+    }
+
+    let prefix = key.iter().zip(&known).take_while(|(a, b)| a == b).count();
+    let suffix = key[prefix..].iter().rev().zip(known[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    key.len() - prefix - suffix <= 1 && known.len() - prefix - suffix <= 1
+}
+
+/// The `|` separated values of a filter.
+fn alternatives<'a>(key: &str, value: &'a str) -> Result<impl Iterator<Item = &'a str>, ParseError> {
+    match value.split('|').any(str::is_empty) {
+        true => Err(ParseError(format!("`{key}:` needs a value, or several separated by `|`"))),
+        false => Ok(value.split('|')),
+    }
+}
+
+fn parse_type(ty: &str) -> Result<Type, ParseError> {
+    match ty.to_lowercase().as_str() {
+        "dir" | "directory" | "folder" => Ok(Type::Dir),
+        "file" => Ok(Type::File),
+        "symlink" | "link" => Ok(Type::Symlink),
+        "app" | "desktop" => Ok(Type::App),
+        "exec" | "executable" => Ok(Type::Exec),
+        _ => Err(ParseError(format!("unknown type `{ty}`, expected dir, file, symlink, app or exec"))),
+    }
+}
+
+/// A user ID, or the ID of a user name from `/etc/passwd`.
+fn parse_owner(owner: &str) -> Result<u32, ParseError> {
+    if let Ok(uid) = owner.parse() {
+        return Ok(uid);
+    }
+
+    let passwd = fs::read_to_string("/etc/passwd").unwrap_or_default();
+    passwd
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields[0] == owner)
+        .and_then(|fields| fields.get(2)?.parse().ok())
+        .ok_or_else(|| ParseError(format!("unknown user `{owner}`")))
+}
+
+fn parse_sort(sort: &str) -> Result<Sort, ParseError> {
+    let (key, direction) = match sort.rsplit_once('-') {
+        Some((key, direction @ ("asc" | "desc"))) => (key, Some(direction == "desc")),
+        _ => (sort, None),
+    };
+
+    let key = match key {
+        "name" => SortKey::Name,
+        "path" => SortKey::Path,
+        "size" => SortKey::Size,
+        "modified" => SortKey::Modified,
+        "changed" => SortKey::Changed,
+        _ => return Err(ParseError(format!("can't sort by `{key}`, expected name, path, size, modified or changed"))),
+    };
+
+    // Biggest and most recent first, unless asked otherwise
+    let descending = direction.unwrap_or(matches!(key, SortKey::Size | SortKey::Modified | SortKey::Changed));
+    Ok(Sort { key, descending })
+}
+
+fn comparison<'a>(key: &str, value: &'a str) -> Result<(Comparison, &'a str), ParseError> {
+    let operators = [
+        (">=", Comparison::GreaterOrEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">", Comparison::Greater),
+        ("<", Comparison::Less),
+        ("=", Comparison::Equal),
+    ];
+
+    operators
+        .into_iter()
+        .find_map(|(op, comparison)| value.strip_prefix(op).map(|rest| (comparison, rest)))
+        .ok_or_else(|| ParseError(format!("`{key}:` needs a comparison, e.g. `{key}:>{value}`")))
+}
+
+/// Splits e.g. `1.5G` into `1.5` and `G`.
+fn split_unit(value: &str) -> Result<(f64, String), ParseError> {
+    let end = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let number = value[..end].parse::<f64>().map_err(|_| ParseError(format!("`{value}` doesn't start with a number")))?;
+    Ok((number, value[end..].to_lowercase()))
+}
+
+fn parse_size(size: &str) -> Result<u64, ParseError> {
+    let (number, unit) = split_unit(size)?;
+    let power = match unit.trim_end_matches("ib").trim_end_matches('b') {
+        "" => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        _ => return Err(ParseError(format!("unknown size unit in `{size}`, expected K, M, G or T"))),
+    };
+
+    Ok((number * 1024f64.powi(power)) as u64)
+}
+
+fn parse_age(age: &str) -> Result<i64, ParseError> {
+    let (number, unit) = split_unit(age)?;
+    let seconds = match unit.as_str() {
+        "s" => 1,
+        "m" | "min" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "y" => 365 * 24 * 60 * 60,
+        _ => return Err(ParseError(format!("unknown time unit in `{age}`, expected s, m, h, d, w or y"))),
+    };
+
+    Ok((number * seconds as f64) as i64)
+}
+
+/// Escapes `LIKE` wildcards, for patterns using `ESCAPE '\'`.
+pub fn escape_like(text: &str) -> String { text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_") }
+
+impl Term {
+    fn sql(&self, params: &mut Vec<Value>) -> String {
+        let condition = match &self.filter {
+            // Desktop entries are also found by their names and keywords
+            Filter::Text(text) | Filter::Phrase(text) => {
+                let pattern = Value::Text(format!("%{}%", escape_like(text)));
+                params.extend([pattern.clone(), pattern]);
+                "(files.path LIKE ? ESCAPE '\\' OR files.terms LIKE ? ESCAPE '\\')".to_string()
+            }
+            Filter::Ext(extensions) => {
+                let conditions = extensions.iter().map(|ext| {
+                    params.push(Value::Text(format!("%.{}", escape_like(ext))));
+                    "files.name LIKE ? ESCAPE '\\'"
+                });
+                conditions.collect::<Vec<_>>().join(" OR ")
+            }
+            Filter::Type(types) => types
+                .iter()
+                .map(|ty| match ty {
+                    Type::Dir => "files.kind = 'dir'",
+                    Type::File => "files.kind = 'file'",
+                    Type::Symlink => "files.kind = 'symlink'",
+                    Type::App => "files.desktop",
+                    Type::Exec => "files.executable",
+                })
+                .collect::<Vec<_>>()
+                .join(" OR "),
+            Filter::Mime(mimes) => {
+                // A bare type like `image` matches any of its subtypes
+                let conditions = mimes.iter().map(|mime| {
+                    let pattern = escape_like(mime.trim_end_matches("/*"));
+                    params.push(Value::Text(if mime.contains('/') && !mime.ends_with("/*") { pattern } else { format!("{pattern}/%") }));
+                    "files.mime LIKE ? ESCAPE '\\'"
+                });
+                conditions.collect::<Vec<_>>().join(" OR ")
+            }
+            Filter::Owner(uids) => {
+                params.extend(uids.iter().map(|uid| Value::Integer(*uid as i64)));
+                format!("files.owner IN ({})", vec!["?"; uids.len()].join(", "))
+            }
+            Filter::In(dir) if dir.starts_with('/') || dir.starts_with('~') => {
+                let dir = settings::expand(Path::new(dir)).to_string_lossy().trim_end_matches('/').to_string();
+                params.push(Value::Text(format!("{}/%", escape_like(&dir))));
+                "files.path LIKE ? ESCAPE '\\'".to_string()
+            }
+            Filter::In(dir) => {
+                params.push(Value::Text(format!("%/{}/%", escape_like(dir))));
+                "files.path LIKE ? ESCAPE '\\'".to_string()
+            }
+            Filter::Size(comparison, size) => {
+                params.push(Value::Integer(*size as i64));
+                format!("files.size {} ?", comparison.sql())
+            }
+            Filter::Modified(comparison, age) | Filter::Changed(comparison, age) => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+                params.push(Value::Integer(now - age));
+                let column = if matches!(self.filter, Filter::Modified(..)) { "modified" } else { "changed" };
+                format!("files.{column} {} ?", comparison.flipped().sql())
+            }
+        };
+
+        match self.negated {
+            true => format!("NOT ({condition})"),
+            false => format!("({condition})"),
+        }
+    }
+}
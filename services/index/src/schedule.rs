@@ -0,0 +1,194 @@
+//! Runs indexing on a fixed pool of workers, the most pressing directories first.
+//!
+//! Every directory is its own job, queued by its [`Rank`]: changes the watcher saw come
+//! before everything else, then folders by their root's priority (applications, then `$PATH`,
+//! then the rest), then recently modified directories, then shallower ones. Workers run at
+//! idle CPU and IO priority, and pause between entries when the rest of the system is busy.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::sync::{Condvar, LazyLock, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ipsea::log::{error, trace};
+
+use crate::settings::{self, IndexConfig};
+use crate::ty::Progress;
+
+/// How often the load average is read again.
+const LOAD_INTERVAL: Duration = Duration::from_secs(1);
+/// Most the pause between entries is stretched by, however busy the system.
+const MAX_STRETCH: f64 = 10.0;
+
+/// How soon a directory should be indexed, greater is sooner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rank {
+    /// Whether it's being refreshed after a change, rather than indexed in bulk.
+    pub urgent: bool,
+    /// The priority of its root.
+    pub priority: i32,
+    /// Whether it was modified recently.
+    pub recent: bool,
+    /// How deep it is in its root.
+    pub depth: usize,
+}
+
+impl Ord for Rank {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let key = |r: &Self| (r.urgent, r.priority, r.recent, Reverse(r.depth));
+        key(self).cmp(&key(other))
+    }
+}
+
+impl PartialOrd for Rank {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+struct Job {
+    rank: Rank,
+    /// Breaks ties in the order jobs were queued.
+    seq: u64,
+    run: Box<dyn FnOnce() + Send>,
+}
+
+impl Ord for Job {
+    fn cmp(&self, other: &Self) -> Ordering { (self.rank, Reverse(self.seq)).cmp(&(other.rank, Reverse(other.seq))) }
+}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for Job {}
+
+#[derive(Default)]
+struct Queue {
+    jobs: BinaryHeap<Job>,
+    seq: u64,
+    /// Jobs being run right now.
+    active: usize,
+    started: bool,
+}
+
+static QUEUE: LazyLock<Mutex<Queue>> = LazyLock::new(Default::default);
+static READY: Condvar = Condvar::new();
+static SCANNED: AtomicU64 = AtomicU64::new(0);
+static WRITTEN: AtomicU64 = AtomicU64::new(0);
+static LOAD: Mutex<Option<(Instant, f64)>> = Mutex::new(None);
+
+/// Queues `run` to be run by a worker, starting them if they aren't yet.
+pub fn push(rank: Rank, run: impl FnOnce() + Send + 'static) {
+    let mut queue = QUEUE.lock().unwrap();
+    if !queue.started {
+        queue.started = true;
+        let settings = settings::current();
+        for i in 0..settings.workers {
+            let idle = settings.idle_priority;
+            thread::Builder::new().name(format!("indexer-{i}")).spawn(move || work(idle)).unwrap();
+        }
+    }
+
+    queue.seq += 1;
+    let seq = queue.seq;
+    queue.jobs.push(Job { rank, seq, run: Box::new(run) });
+    READY.notify_one();
+}
+
+fn work(idle: bool) {
+    if idle {
+        lower_priority();
+    }
+
+    loop {
+        let job = {
+            let mut queue = QUEUE.lock().unwrap();
+            loop {
+                if let Some(job) = queue.jobs.pop() {
+                    queue.active += 1;
+                    break job;
+                }
+                queue = READY.wait(queue).unwrap();
+            }
+        };
+
+        // A panicking job shouldn't take the worker with it, nor leave the pool looking busy forever
+        if panic::catch_unwind(AssertUnwindSafe(job.run)).is_err() {
+            error!("An indexing job panicked, carrying on with the rest");
+        }
+        QUEUE.lock().unwrap().active -= 1;
+        SCANNED.fetch_add(1, Relaxed);
+    }
+}
+
+/// Drops the calling thread to the lowest CPU priority and the idle IO class,
+/// so indexing only gets the disk when nothing else wants it.
+fn lower_priority() {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+    // SAFETY: Both only change the scheduling of the calling thread, named by its tid (or 0).
+    unsafe {
+        if libc::setpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t, 19) != 0 {
+            error!("Failed to lower CPU priority: {}", std::io::Error::last_os_error());
+        }
+        if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT) != 0 {
+            error!("Failed to lower IO priority: {}", std::io::Error::last_os_error());
+        }
+    }
+}
+
+/// Counts entries written to the index, for [`progress`].
+pub fn wrote(count: u64) { WRITTEN.fetch_add(count, Relaxed); }
+
+/// How far along indexing is.
+pub fn progress() -> Progress {
+    let queue = QUEUE.lock().unwrap();
+    Progress {
+        queued: queue.jobs.len(),
+        active: queue.active,
+        scanned: SCANNED.load(Relaxed),
+        written: WRITTEN.load(Relaxed),
+    }
+}
+
+/// How long to pause after indexing an entry: nothing while the system has room to spare,
+/// otherwise `throttle_ms` stretched by how far the load is over `max_load`.
+pub fn pause(settings: &IndexConfig) -> Duration {
+    let load = load();
+    if load <= settings.max_load {
+        return Duration::ZERO;
+    }
+
+    Duration::from_millis(settings.throttle_ms).mul_f64((load / settings.max_load).min(MAX_STRETCH))
+}
+
+/// The one minute load average per CPU, less what the workers themselves contribute.
+fn load() -> f64 {
+    let mut cached = LOAD.lock().unwrap();
+    if let Some((at, load)) = *cached {
+        if at.elapsed() < LOAD_INTERVAL {
+            return load;
+        }
+    }
+
+    let average = fs::read_to_string("/proc/loadavg")
+        .ok()
+        .and_then(|s| s.split_whitespace().next()?.parse::<f64>().ok())
+        .unwrap_or_default();
+    let cpus = thread::available_parallelism().map_or(1, |n| n.get()) as f64;
+    let active = QUEUE.lock().unwrap().active as f64;
+
+    let load = (average - active).max(0.0) / cpus;
+    trace!("Load is {load:.2} per CPU");
+    *cached = Some((Instant::now(), load));
+    load
+}
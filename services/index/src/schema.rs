@@ -0,0 +1,215 @@
+//! Versioned migrations of `index.db`.
+//!
+//! The schema's version is kept in `PRAGMA user_version`, and [`migrate`] runs every
+//! migration past it in turn, each in its own transaction, so new columns or tables
+//! don't mean deleting the index. Databases from before versioning are all at 0, so
+//! which migrations they already have is worked out from their tables and columns.
+//!
+//! Migrations are only ever appended, never changed once released.
+
+use std::fmt::{self, Display};
+
+use rusqlite::{Connection, Transaction};
+
+type Migration = fn(&Transaction) -> rusqlite::Result<()>;
+
+/// Every migration, the `n`th taking the schema from version `n` to `n + 1`.
+const MIGRATIONS: [Migration; 7] = [files, contents, names, metadata, parents, opens, desktop_entries];
+
+/// The version of the schema [`migrate`] brings databases up to.
+pub const VERSION: u32 = MIGRATIONS.len() as u32;
+
+#[derive(Debug)]
+pub enum SchemaError {
+    /// The database was written by a newer version, whose schema this one doesn't know.
+    TooNew(u32),
+    Sqlite(rusqlite::Error),
+}
+
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::TooNew(version) => {
+                write!(f, "The index is at schema version {version}, newer than the {VERSION} this version knows")
+            }
+            SchemaError::Sqlite(e) => write!(f, "Failed to migrate the index: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl From<rusqlite::Error> for SchemaError {
+    fn from(e: rusqlite::Error) -> Self { SchemaError::Sqlite(e) }
+}
+
+/// The schema version `conn` is at.
+pub fn version(conn: &Connection) -> rusqlite::Result<u32> { conn.query_row("PRAGMA user_version", [], |row| row.get(0)) }
+
+/// Brings the schema of `conn` up to [`VERSION`], creating it if it's empty.
+pub fn migrate(conn: &mut Connection) -> Result<(), SchemaError> {
+    let mut current = match version(conn)? {
+        0 => {
+            let detected = detect(conn)?;
+            conn.pragma_update(None, "user_version", detected)?;
+            detected
+        }
+        version if version > VERSION => return Err(SchemaError::TooNew(version)),
+        version => version,
+    };
+
+    while current < VERSION {
+        let tx = conn.transaction()?;
+        MIGRATIONS[current as usize](&tx)?;
+        current += 1;
+        tx.pragma_update(None, "user_version", current)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// The version an unversioned database is at, going by the last migration whose changes it has.
+fn detect(conn: &Connection) -> rusqlite::Result<u32> {
+    let has_table = |table: &str| conn.prepare("SELECT 1 FROM sqlite_master WHERE name = ?1")?.exists([table]);
+    let has_column = |column: &str| conn.prepare("SELECT 1 FROM pragma_table_info('files') WHERE name = ?1")?.exists([column]);
+
+    Ok(if has_column("parent")? {
+        5
+    } else if has_column("kind")? {
+        4
+    } else if has_table("names")? {
+        3
+    } else if has_table("contents")? {
+        2
+    } else if has_table("files")? {
+        1
+    } else {
+        0
+    })
+}
+
+fn files(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE files (
+            name TEXT NOT NULL,
+            path TEXT PRIMARY KEY,
+            depth INTEGER NOT NULL,
+            executable BOOL NOT NULL,
+            desktop BOOL NOT NULL,
+            icon TEXT,
+            last_accessed INTEGER NOT NULL
+        );",
+    )
+}
+
+/// Full-text search over file contents.
+fn contents(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE contents USING fts5(
+            path UNINDEXED,
+            body
+        );",
+    )
+}
+
+/// Trigram index over paths (and so names) for substring search, backed by
+/// `files` itself and kept in sync with it by triggers.
+fn names(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE names USING fts5(
+            path,
+            content = 'files',
+            content_rowid = 'rowid',
+            tokenize = 'trigram'
+        );
+
+        CREATE TRIGGER names_insert AFTER INSERT ON files BEGIN
+            INSERT INTO names (rowid, path) VALUES (new.rowid, new.path);
+        END;
+        CREATE TRIGGER names_delete AFTER DELETE ON files BEGIN
+            INSERT INTO names (names, rowid, path) VALUES ('delete', old.rowid, old.path);
+        END;
+        CREATE TRIGGER names_update AFTER UPDATE OF path ON files BEGIN
+            INSERT INTO names (names, rowid, path) VALUES ('delete', old.rowid, old.path);
+            INSERT INTO names (rowid, path) VALUES (new.rowid, new.path);
+        END;
+
+        -- Catching up on files indexed before
+        INSERT INTO names (names) VALUES ('rebuild');",
+    )
+}
+
+/// What's stored about each file beyond its name, which files indexed before get once they're reindexed.
+fn metadata(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        // It was always when the file was indexed, not accessed
+        "ALTER TABLE files RENAME COLUMN last_accessed TO indexed_at;
+
+        ALTER TABLE files ADD COLUMN kind TEXT NOT NULL DEFAULT 'file';
+        ALTER TABLE files ADD COLUMN size INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE files ADD COLUMN modified INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE files ADD COLUMN changed INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE files ADD COLUMN inode INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE files ADD COLUMN device INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE files ADD COLUMN owner INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE files ADD COLUMN mime TEXT;",
+    )
+}
+
+/// Each file's directory, as walks diff each directory against what's indexed within it.
+fn parents(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE files ADD COLUMN parent TEXT NOT NULL DEFAULT '';
+        UPDATE files SET parent = rtrim(rtrim(path, replace(path, '/', '')), '/');
+        CREATE INDEX files_parent ON files (parent);",
+    )
+}
+
+/// How often and recently each file was opened, for ranking by frecency. Kept apart from
+/// `files` so reindexing never touches it.
+fn opens(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE opens (
+            path TEXT PRIMARY KEY,
+            count INTEGER NOT NULL,
+            last_opened INTEGER NOT NULL
+        );",
+    )
+}
+
+/// Desktop Entries as parsed, and what else they're searched by, which the trigram index
+/// is rebuilt to cover as well. Entries indexed before are marked changed, to be read again.
+fn desktop_entries(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE files ADD COLUMN entry TEXT;
+        ALTER TABLE files ADD COLUMN terms TEXT NOT NULL DEFAULT '';
+        UPDATE files SET modified = 0 WHERE desktop;
+
+        DROP TRIGGER names_insert;
+        DROP TRIGGER names_delete;
+        DROP TRIGGER names_update;
+        DROP TABLE names;
+
+        CREATE VIRTUAL TABLE names USING fts5(
+            path,
+            terms,
+            content = 'files',
+            content_rowid = 'rowid',
+            tokenize = 'trigram'
+        );
+
+        CREATE TRIGGER names_insert AFTER INSERT ON files BEGIN
+            INSERT INTO names (rowid, path, terms) VALUES (new.rowid, new.path, new.terms);
+        END;
+        CREATE TRIGGER names_delete AFTER DELETE ON files BEGIN
+            INSERT INTO names (names, rowid, path, terms) VALUES ('delete', old.rowid, old.path, old.terms);
+        END;
+        CREATE TRIGGER names_update AFTER UPDATE OF path, terms ON files BEGIN
+            INSERT INTO names (names, rowid, path, terms) VALUES ('delete', old.rowid, old.path, old.terms);
+            INSERT INTO names (rowid, path, terms) VALUES (new.rowid, new.path, new.terms);
+        END;
+
+        INSERT INTO names (names) VALUES ('rebuild');",
+    )
+}
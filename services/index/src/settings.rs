@@ -0,0 +1,233 @@
+use std::{
+    env::{self, var},
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use anyhow::Result;
+use config::{migrate, ty::App, FieldError, Validate};
+use serde_json::Value;
+use ignore::gitignore::GitignoreBuilder;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Config for the index service, loaded from the `IndexService` config file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct IndexConfig {
+    /// Folders to index.
+    pub roots: Vec<Root>,
+    /// Also index (shallowly) every folder in `$PATH`.
+    pub include_path: bool,
+    /// How deep to recurse into roots without their own `depth`.
+    pub max_depth: usize,
+    /// Regexes matched against full paths; matching folders are skipped.
+    pub ignore: Vec<String>,
+    /// Skip whatever `.gitignore`, `.ignore` and `.finickignore` files exclude, see [`crate::ignores`].
+    pub ignore_files: bool,
+    /// Gitignore-style globs skipped everywhere, e.g. `*.pyc` or `__pycache__/`.
+    pub ignore_globs: Vec<String>,
+    /// File extensions never indexed, compared case-insensitively.
+    pub excluded_extensions: Vec<String>,
+    /// Most results returned for a single search.
+    pub max_results: usize,
+    /// Pause between indexing entries once the system is busier than `max_load`,
+    /// growing with the load, to keep the service in the background.
+    pub throttle_ms: u64,
+    /// Load average per CPU, not counting indexing itself, above which indexing is throttled.
+    pub max_load: f64,
+    /// Directories indexed at once. Takes effect on restart.
+    pub workers: usize,
+    /// Index at the lowest CPU and idle IO priority. Takes effect on restart.
+    pub idle_priority: bool,
+    /// Also index the contents of text-like files, for full-text search.
+    pub index_contents: bool,
+    /// Largest file, in bytes, whose contents are indexed.
+    pub content_max_size: u64,
+    /// Extensions of files whose contents are indexed, compared case-insensitively.
+    pub content_extensions: Vec<String>,
+    /// Icon theme desktop entries' icons are looked up in, before `hicolor`. Defaults to GTK's.
+    pub icon_theme: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Root {
+    /// Folder to index, `~/` is expanded to the home directory.
+    pub path: PathBuf,
+    /// Overrides `max_depth` for this root.
+    #[serde(default)]
+    pub depth: Option<usize>,
+    /// Overrides how soon this root is indexed relative to others, higher is sooner.
+    /// Defaults to 2 for application folders and 0 otherwise, `$PATH` gets 1.
+    #[serde(default)]
+    pub priority: Option<i32>,
+}
+
+/// A folder to index, resolved from a [`Root`] or `$PATH`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Folder {
+    pub path: PathBuf,
+    pub max_depth: usize,
+    pub priority: i32,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            // Only the ones present here, so the defaults always pass validation.
+            roots: ["~/.local/share/applications/", "/usr/share/applications/", "/usr/local/share/applications/", "~"]
+                .map(Root::new)
+                .into_iter()
+                .filter(|r| r.expanded().is_dir())
+                .collect(),
+            include_path: true,
+            max_depth: 5,
+            ignore: vec!["node_modules/.+".into(), "target/.+".into(), r"/\..+".into()],
+            ignore_files: true,
+            ignore_globs: vec![],
+            excluded_extensions: ["exe", "bin", "o", "dll", "so", "dat", "class", "rmeta", "rlib", "d"]
+                .map(String::from)
+                .to_vec(),
+            max_results: 100,
+            throttle_ms: 100,
+            max_load: 0.75,
+            workers: std::thread::available_parallelism().map_or(1, |n| n.get() / 2).clamp(1, 4),
+            idle_priority: true,
+            index_contents: false,
+            content_max_size: 1024 * 1024,
+            content_extensions: [
+                "txt", "md", "markdown", "rst", "org", "tex", "rs", "py", "js", "ts", "jsx", "tsx", "go", "c", "h", "cpp",
+                "hpp", "cc", "java", "kt", "swift", "rb", "php", "lua", "sh", "bash", "zsh", "fish", "nix", "sql", "html",
+                "css", "scss", "xml", "toml", "yaml", "yml", "json", "ini", "conf", "cfg", "csv",
+            ]
+            .map(String::from)
+            .to_vec(),
+            icon_theme: None,
+        }
+    }
+}
+
+impl Root {
+    pub fn new(path: impl Into<PathBuf>) -> Self { Self { path: path.into(), depth: None, priority: None } }
+
+    pub fn expanded(&self) -> PathBuf { expand(&self.path) }
+}
+
+impl IndexConfig {
+    /// Every folder to index, with how deep to go and how soon.
+    pub fn folders(&self) -> Vec<Folder> {
+        let mut folders = self
+            .roots
+            .iter()
+            .map(|r| {
+                let path = r.expanded();
+                let priority = r.priority.unwrap_or(if path.ends_with("applications") { 2 } else { 0 });
+                Folder { path, max_depth: r.depth.unwrap_or(self.max_depth), priority }
+            })
+            .collect::<Vec<_>>();
+
+        if self.include_path {
+            if let Ok(path) = var("PATH") {
+                folders.extend(env::split_paths(&path).map(|path| Folder { path, max_depth: 0, priority: 1 }));
+            }
+        }
+
+        folders
+    }
+
+    /// The deepest of [`IndexConfig::folders`] containing `path`, along with how deep `path` is within it.
+    pub fn folder_of(&self, path: &Path) -> Option<(Folder, usize)> {
+        self.folders()
+            .into_iter()
+            .filter_map(|folder| {
+                let depth = path.strip_prefix(&folder.path).ok()?.components().count();
+                Some((folder, depth))
+            })
+            .min_by_key(|(_, depth)| *depth)
+    }
+
+    /// The ignore patterns which compile, invalid ones are rejected by validation.
+    pub fn ignore_patterns(&self) -> Vec<Regex> { self.ignore.iter().filter_map(|p| Regex::new(p).ok()).collect() }
+
+    /// Whether the contents of `path` should be indexed, going by its extension.
+    pub fn indexes_contents(&self, path: &Path) -> bool {
+        self.index_contents
+            && path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .is_some_and(|ext| self.content_extensions.iter().any(|e| e.eq_ignore_ascii_case(&ext)))
+    }
+
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        path.extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .is_some_and(|ext| self.excluded_extensions.iter().any(|e| e.eq_ignore_ascii_case(&ext)))
+    }
+}
+
+impl Validate for IndexConfig {
+    fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        for (i, root) in self.roots.iter().enumerate() {
+            if !root.expanded().is_dir() {
+                errors.push(FieldError::new(format!("roots[{i}].path"), "path does not exist"));
+            }
+        }
+
+        for (i, pattern) in self.ignore.iter().enumerate() {
+            if let Err(e) = Regex::new(pattern) {
+                errors.push(FieldError::new(format!("ignore[{i}]"), e));
+            }
+        }
+
+        for (i, glob) in self.ignore_globs.iter().enumerate() {
+            if let Err(e) = GitignoreBuilder::new("/").add_line(None, glob) {
+                errors.push(FieldError::new(format!("ignore_globs[{i}]"), e));
+            }
+        }
+
+        if self.max_results == 0 {
+            errors.push(FieldError::new("max_results", "must be at least 1"));
+        }
+
+        if self.workers == 0 {
+            errors.push(FieldError::new("workers", "must be at least 1"));
+        }
+
+        if self.max_load <= 0.0 {
+            errors.push(FieldError::new("max_load", "must be above 0"));
+        }
+
+        errors
+    }
+}
+
+/// Upgrades of `IndexService` config files, the `n`th from version `n` to `n + 1`.
+const MIGRATIONS: [migrate::Migration; 1] = [drop_reindex_threshold];
+
+/// Registers [`MIGRATIONS`], before the config is first read.
+pub fn register_migrations() { migrate::register(App::IndexService, MIGRATIONS.to_vec()) }
+
+/// Version 1: `reindex_threshold` went once directories were diffed against the index instead.
+fn drop_reindex_threshold(value: &mut Value) -> Result<()> {
+    if let Value::Object(map) = value {
+        map.remove("reindex_threshold");
+    }
+    Ok(())
+}
+
+pub(crate) fn expand(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), var("HOME")) {
+        (Ok(rest), Ok(home)) => Path::new(&home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+static CURRENT: LazyLock<RwLock<Arc<IndexConfig>>> = LazyLock::new(Default::default);
+
+/// The config currently in effect.
+pub fn current() -> Arc<IndexConfig> { CURRENT.read().unwrap().clone() }
+
+/// Replaces the config in effect, e.g. after a reload.
+pub fn set(config: IndexConfig) { *CURRENT.write().unwrap() = Arc::new(config); }
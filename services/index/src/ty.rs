@@ -1,16 +1,150 @@
+use std::str::FromStr;
+
 use serde::{Deserialize, Serialize};
 
+use crate::desktop::DesktopEntry;
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct Request {
-    pub query: String,
+pub enum Request {
+    Search {
+        /// See [`crate::query`] for the syntax.
+        query: String,
+        #[serde(default)]
+        scope: Scope,
+    },
+    /// How far along indexing is, answered with a single [`Response::Progress`].
+    Progress,
+    /// A file was opened, which ranks it higher in searches for a while. Nothing is sent back.
+    Opened { path: String },
+}
+
+/// What a search matches the query against.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scope {
+    /// File names and paths.
+    Names,
+    /// Indexed file contents.
+    Contents,
+    #[default]
+    Both,
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "names" | "name" => Ok(Scope::Names),
+            "contents" | "content" => Ok(Scope::Contents),
+            "both" | "all" => Ok(Scope::Both),
+            _ => Err(format!("Unknown scope `{s}`, expected names, contents or both")),
+        }
+    }
 }
 
+/// What the index service sends back for a [`Request`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Response {
+    Result(SearchResult),
+    /// The request couldn't be carried out, e.g. its query didn't parse.
+    Error(String),
+    Progress(Progress),
+}
+
+/// How far along the index service is with indexing.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Directories waiting to be indexed.
+    pub queued: usize,
+    /// Directories being indexed right now.
+    pub active: usize,
+    /// Directories indexed since the service started.
+    pub scanned: u64,
+    /// Entries added, updated or removed since the service started.
+    pub written: u64,
+}
+
+impl Progress {
+    pub fn is_idle(&self) -> bool { self.queued == 0 && self.active == 0 }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SearchResult {
     pub name: String,
     pub path: String,
+    #[serde(default)]
+    pub is_dir: bool,
     pub is_desktop: bool,
     pub is_executable: bool,
     pub icon: Option<String>,
+    /// The matching line, for results found by their contents.
+    #[serde(default)]
+    pub snippet: Option<Snippet>,
+    /// How well the name or path fuzzily matched, higher is better.
+    /// Results found only by their contents score 0.
+    #[serde(default)]
+    pub score: i64,
+    #[serde(default)]
+    pub metadata: Metadata,
+    /// The parsed entry, for desktop entries.
+    #[serde(default)]
+    pub entry: Option<Box<DesktopEntry>>,
+}
+
+/// A result's filesystem metadata, as of when it was indexed.
+/// Symlinks describe the link itself, not what it points to.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub kind: Kind,
+    /// In bytes.
+    pub size: u64,
+    /// Last modified, as a Unix timestamp.
+    pub modified: i64,
+    /// Last status change (e.g. permissions or links), as a Unix timestamp.
+    pub changed: i64,
+    pub inode: u64,
+    pub device: u64,
+    /// User ID of the owner.
+    pub owner: u32,
+    /// Guessed from the extension.
+    pub mime: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Kind {
+    #[default]
+    File,
+    Dir,
+    Symlink,
+}
+
+impl Kind {
+    /// How it's stored in the index.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::File => "file",
+            Kind::Dir => "dir",
+            Kind::Symlink => "symlink",
+        }
+    }
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(Kind::File),
+            "dir" => Ok(Kind::Dir),
+            "symlink" => Ok(Kind::Symlink),
+            _ => Err(format!("Unknown kind `{s}`, expected file, dir or symlink")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Snippet {
+    /// 1-based line number.
+    pub line: usize,
+    pub text: String,
 }
@@ -0,0 +1,225 @@
+//! Keeps the index in step with the filesystem as it changes.
+//!
+//! Events are gathered for a moment so bursts (an unpacked archive, a build) are handled
+//! together. Renames rewrite the paths of whatever was moved, subtrees included, rather than
+//! reindexing it; any other change refreshes the directories it happened in. If the watcher
+//! reports an error or that it dropped events, the roots affected are rescanned in full, as
+//! is any directory whose ignore files changed. When the config is reloaded, the watches are
+//! moved over to the new roots.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ipsea::log::{error, trace};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, Connection};
+
+use crate::ignores;
+use crate::query::escape_like;
+use crate::settings::{self, IndexConfig};
+
+/// How long to wait for another event before handling those gathered so far.
+const SETTLE: Duration = Duration::from_millis(200);
+/// Longest to keep gathering events for while they keep coming.
+const SETTLE_MAX: Duration = Duration::from_secs(2);
+
+enum Message {
+    Event(notify::Result<Event>),
+    /// The roots changed, see [`rewatch`].
+    Rewatch,
+}
+
+/// Watches every configured root, updating the index as things change, and watches the
+/// roots afresh whenever `roots_changed` is sent to. Blocks for as long as the watcher runs.
+pub fn watch(pool: Pool<SqliteConnectionManager>, roots_changed: Receiver<()>) {
+    let (tx, rx) = mpsc::channel();
+    let watcher = Watcher::new(
+        {
+            let tx = tx.clone();
+            move |event| {
+                let _ = tx.send(Message::Event(event));
+            }
+        },
+        Config::default(),
+    );
+    let mut watcher: RecommendedWatcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => return error!("Failed to start watching, changes won't be seen until the next full index: {e}"),
+    };
+    thread::spawn(move || roots_changed.iter().try_for_each(|()| tx.send(Message::Rewatch)));
+
+    let mut watched = vec![];
+    rewatch(&mut watcher, &mut watched);
+
+    while let Ok(first) = rx.recv() {
+        let start = Instant::now();
+        let mut messages = vec![first];
+        while start.elapsed() < SETTLE_MAX {
+            match rx.recv_timeout(SETTLE) {
+                Ok(message) => messages.push(message),
+                Err(_) => break,
+            }
+        }
+
+        let mut events = vec![];
+        for message in messages {
+            match message {
+                Message::Event(event) => events.push(event),
+                Message::Rewatch => rewatch(&mut watcher, &mut watched),
+            }
+        }
+        if !events.is_empty() {
+            handle(events, &pool);
+        }
+    }
+}
+
+/// Stops watching the roots in `watched` which are no longer configured and starts on the new
+/// ones. Roots which aren't recursed into are only watched themselves.
+fn rewatch(watcher: &mut RecommendedWatcher, watched: &mut Vec<(PathBuf, RecursiveMode)>) {
+    let roots = settings::current()
+        .folders()
+        .into_iter()
+        .filter(|f| f.path.is_dir())
+        .map(|f| (f.path, if f.max_depth == 0 { RecursiveMode::NonRecursive } else { RecursiveMode::Recursive }))
+        .collect::<Vec<_>>();
+
+    for (root, _) in watched.iter().filter(|w| !roots.contains(w)) {
+        trace!("Unwatching {}", root.display());
+        if let Err(e) = watcher.unwatch(root) {
+            error!("Failed to stop watching {}: {e}", root.display());
+        }
+    }
+    watched.retain(|w| roots.contains(w));
+
+    for (root, mode) in roots {
+        if watched.iter().any(|(w, _)| *w == root) {
+            continue;
+        }
+        trace!("Watching {}", root.display());
+        match watcher.watch(&root, mode) {
+            Ok(()) => watched.push((root, mode)),
+            Err(e) => error!("Failed to watch {}: {e}", root.display()),
+        }
+    }
+}
+
+fn handle(events: Vec<notify::Result<Event>>, pool: &Pool<SqliteConnectionManager>) {
+    let settings = settings::current();
+    // The index's own database and icon cache, whose writes would otherwise set off more indexing
+    let own = [config::data_dir(), config::cache_dir()].into_iter().flatten().collect::<Vec<_>>();
+
+    let mut renames = vec![];
+    let mut dirty = BTreeSet::new();
+    let mut rescan = BTreeSet::new();
+
+    for event in events {
+        let event = match event {
+            Ok(event) if event.need_rescan() => {
+                error!("Watcher dropped events, rescanning");
+                rescan.extend(roots_of(&settings, &event.paths));
+                continue;
+            }
+            Err(e) => {
+                error!("Watcher failed, rescanning: {e}");
+                rescan.extend(roots_of(&settings, &e.paths));
+                continue;
+            }
+            Ok(event) => event,
+        };
+
+        let paths = event.paths.into_iter().filter(|p| !own.iter().any(|o| p.starts_with(o))).collect::<Vec<_>>();
+        match (event.kind, paths.as_slice()) {
+            (EventKind::Access(_), _) => continue,
+            (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) => renames.push((from.clone(), to.clone())),
+            _ => {}
+        }
+
+        dirty.extend(paths.iter().filter_map(|p| p.parent()).map(Path::to_path_buf));
+
+        // Changed ignore rules reach anywhere below them, so their directory is rescanned
+        let rules = paths.iter().filter(|p| p.file_name().is_some_and(|n| ignores::FILES.iter().any(|f| n == *f)));
+        rescan.extend(rules.filter_map(|p| p.parent()).map(Path::to_path_buf));
+    }
+
+    // Failed moves are still seen by the refreshes of their parents, as a removal and an addition
+    if !renames.is_empty() {
+        match pool.get() {
+            Ok(mut conn) => {
+                for (from, to) in renames {
+                    if let Err(e) = rename(&mut conn, &settings, &from, &to) {
+                        error!("Failed to move {} to {}: {e}", from.display(), to.display());
+                    }
+                }
+            }
+            Err(e) => error!("Failed to move {} entries: {e}", renames.len()),
+        }
+    }
+
+    // Rescanned roots cover anything else which changed within them
+    dirty.retain(|dir| !rescan.iter().any(|root| dir.starts_with(root)));
+
+    if !rescan.is_empty() {
+        crate::index(Some(rescan.into_iter().collect()), pool.clone());
+    }
+    if !dirty.is_empty() {
+        trace!("Refreshing {} directories", dirty.len());
+        crate::refresh(dirty.into_iter().collect(), pool.clone());
+    }
+}
+
+/// The roots containing `paths`, or every root if there are none to go by.
+fn roots_of(settings: &IndexConfig, paths: &[PathBuf]) -> Vec<PathBuf> {
+    match paths {
+        [] => settings.folders().into_iter().map(|folder| folder.path).collect(),
+        paths => paths.iter().filter_map(|p| settings.folder_of(p)).map(|(folder, _)| folder.path).collect(),
+    }
+}
+
+/// Moves whatever's indexed at `from`, and under it, to `to`. Moves into or out of the
+/// roots are left to the refreshes of their parents, which see additions and removals.
+fn rename(conn: &mut Connection, settings: &IndexConfig, from: &Path, to: &Path) -> rusqlite::Result<()> {
+    let (Some((_, from_depth)), Some((_, to_depth))) = (settings.folder_of(from), settings.folder_of(to)) else {
+        return Ok(());
+    };
+
+    trace!("Moving {} to {}", from.display(), to.display());
+    let to_parent = to.parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+    let to_name = to.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let (from, to) = (from.to_string_lossy(), to.to_string_lossy());
+    let under = |path: &str| format!("{}/%", escape_like(path));
+
+    let tx = conn.transaction()?;
+
+    // Whatever was at `to` has been replaced
+    tx.execute("DELETE FROM files WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'", params![to, under(&to)])?;
+    tx.execute("DELETE FROM contents WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'", params![to, under(&to)])?;
+    tx.execute("DELETE FROM opens WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'", params![to, under(&to)])?;
+
+    tx.execute(
+        "UPDATE files SET
+            path = ?2 || substr(path, length(?1) + 1),
+            parent = CASE WHEN path = ?1 THEN ?3 ELSE ?2 || substr(parent, length(?1) + 1) END,
+            name = CASE WHEN path = ?1 AND NOT desktop THEN ?4 ELSE name END,
+            depth = depth + ?5
+        WHERE path = ?1 OR path LIKE ?6 ESCAPE '\\'",
+        params![from, to, to_parent, to_name, to_depth as i64 - from_depth as i64, under(&from)],
+    )?;
+    tx.execute(
+        "UPDATE contents SET path = ?2 || substr(path, length(?1) + 1) WHERE path = ?1 OR path LIKE ?3 ESCAPE '\\'",
+        params![from, to, under(&from)],
+    )?;
+    // Files keep how often they were opened wherever they go
+    tx.execute(
+        "UPDATE opens SET path = ?2 || substr(path, length(?1) + 1) WHERE path = ?1 OR path LIKE ?3 ESCAPE '\\'",
+        params![from, to, under(&from)],
+    )?;
+
+    tx.commit()
+}
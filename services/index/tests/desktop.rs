@@ -0,0 +1,176 @@
+//! Parsing desktop entries, see `src/desktop.rs`.
+
+use std::{env, fs, process};
+
+use index::desktop::{self, Action, DesktopEntry};
+
+const LOCALE: Option<&str> = Some("de_DE.UTF-8@euro");
+
+fn parse(source: &str, locale: Option<&str>) -> DesktopEntry {
+    DesktopEntry::parse(source, locale).unwrap_or_else(|| panic!("No entry in:\n{source}"))
+}
+
+/// The `Name` picked for [`LOCALE`] from `names`, each a line like `Name[de]=...`.
+fn localized_name(names: &[&str]) -> String {
+    let source = format!("[Desktop Entry]\n{}\n", names.join("\n"));
+    parse(&source, LOCALE).name
+}
+
+#[test]
+fn needs_a_desktop_entry_group() {
+    assert_eq!(DesktopEntry::parse("[Desktop Action new]\nName=New\n", None), None);
+    assert_eq!(DesktopEntry::parse("Name=Loose\n", None), None);
+}
+
+#[test]
+fn picks_the_closest_locale() {
+    let names = ["Name=Files", "Name[de]=Dateien (de)", "Name[de@euro]=Dateien (de@euro)", "Name[de_DE]=Dateien (de_DE)"];
+    let all = [&names[..], &["Name[de_DE@euro]=Dateien (de_DE@euro)"]].concat();
+
+    // Best first: lang_COUNTRY@MODIFIER, lang_COUNTRY, lang@MODIFIER, lang, then unlocalized
+    assert_eq!(localized_name(&all), "Dateien (de_DE@euro)");
+    assert_eq!(localized_name(&names), "Dateien (de_DE)");
+    assert_eq!(localized_name(&[names[0], names[1], names[2]]), "Dateien (de@euro)");
+    assert_eq!(localized_name(&[names[0], names[1]]), "Dateien (de)");
+    assert_eq!(localized_name(&[names[0], "Name[fr]=Fichiers", "Name[de_AT]=Dateien (de_AT)"]), "Files");
+
+    // Without a locale, or a locale without a country or modifier
+    assert_eq!(parse(&format!("[Desktop Entry]\n{}\n", all.join("\n")), None).name, "Files");
+    assert_eq!(parse(&format!("[Desktop Entry]\n{}\n", all.join("\n")), Some("de")).name, "Dateien (de)");
+}
+
+#[test]
+fn localizes_keywords_but_not_exec() {
+    let entry = parse(
+        "[Desktop Entry]\nName=Files\nKeywords=folder;\nKeywords[de]=Ordner;\nExec=files\nExec[de]=dateien\n",
+        LOCALE,
+    );
+    assert_eq!(entry.keywords, ["Ordner"]);
+    assert_eq!(entry.exec.as_deref(), Some("files"));
+}
+
+#[test]
+fn splits_lists_with_escaped_semicolons() {
+    let entry = parse(
+        "[Desktop Entry]\nName=Calc\nKeywords=a\\;b;c;;\\sd;\nCategories=Office;Math\nOnlyShowIn=GNOME;KDE;\n",
+        None,
+    );
+    assert_eq!(entry.keywords, ["a;b", "c", " d"]);
+    assert_eq!(entry.categories, ["Office", "Math"]);
+    assert_eq!(entry.only_show_in, ["GNOME", "KDE"]);
+}
+
+#[test]
+fn unescapes_strings() {
+    let entry = parse("[Desktop Entry]\nName=Two\\sWords\nComment=Line\\nbreak\\\\\n", None);
+    assert_eq!(entry.name, "Two Words");
+    assert_eq!(entry.comment.as_deref(), Some("Line\nbreak\\"));
+}
+
+#[test]
+fn keeps_actions_out_of_the_main_entry() {
+    let source = "\
+[Desktop Action private]
+Name=New Private Window
+Exec=browser --private
+Icon=private
+
+[Desktop Entry]
+Name=Browser
+Exec=browser %u
+Actions=new-window;private;missing;
+
+[Desktop Action new-window]
+Name=New Window
+Name[de]=Neues Fenster
+Exec=browser --new-window
+Keywords=window;
+NoDisplay=true
+
+[Desktop Action unlisted]
+Name=Unlisted
+";
+    let entry = parse(source, LOCALE);
+
+    assert_eq!(entry.name, "Browser");
+    assert_eq!(entry.exec.as_deref(), Some("browser %u"));
+    assert_eq!(entry.icon, None);
+    assert!(entry.keywords.is_empty());
+    assert!(!entry.no_display);
+
+    // Only listed actions with a group of their own, in the order listed
+    assert_eq!(entry.actions, [
+        Action {
+            id: "new-window".into(),
+            name: "Neues Fenster".into(),
+            icon: None,
+            exec: Some("browser --new-window".into())
+        },
+        Action {
+            id: "private".into(),
+            name: "New Private Window".into(),
+            icon: Some("private".into()),
+            exec: Some("browser --private".into())
+        },
+    ]);
+}
+
+#[test]
+fn ignores_comments_and_blank_lines() {
+    let entry = parse("# A comment\n\n[Desktop Entry]\n# Name=Commented\nName = Spaced \nTerminal=true\n", None);
+    assert_eq!(entry.name, "Spaced");
+    assert!(entry.terminal);
+}
+
+#[test]
+fn expands_exec_field_codes() {
+    let entry = parse(
+        "[Desktop Entry]\nName=Editor\nIcon=editor\nExec=edit --title=%c %i %F --line=%% %d\n",
+        None,
+    );
+    assert_eq!(entry.command(&["a.txt", "b.txt"]).unwrap(), [
+        "edit", "--title=Editor", "--icon", "editor", "a.txt", "b.txt", "--line=%"
+    ]);
+
+    // Quoted arguments keep their spaces, with quotes in them escaped as `\\"` in the file
+    let entry = parse("[Desktop Entry]\nName=Shell\nExec=sh -c \"echo \\\\\"hi there\\\\\"\" %u\n", None);
+    assert_eq!(entry.command(&[]).unwrap(), ["sh", "-c", "echo \"hi there\""]);
+
+    assert_eq!(parse("[Desktop Entry]\nName=None\n", None).command(&[]), None);
+}
+
+#[test]
+fn searches_by_name_generic_name_and_keywords() {
+    let entry = parse("[Desktop Entry]\nName=Firefox\nGenericName=Web Browser\nKeywords=internet;www;\n", None);
+    assert_eq!(entry.terms(), "Firefox Web Browser internet www");
+}
+
+#[test]
+fn overrides_entries_by_desktop_file_id() {
+    let root = env::temp_dir().join(format!("finick-desktop-{}", process::id()));
+    let (user, system) = (root.join("user"), root.join("system"));
+    for (dir, files) in [
+        (&user, &["applications/zorkmail.desktop", "applications/kde-zorkedit.desktop"][..]),
+        (&system, &["applications/zorkmail.desktop", "applications/kde/zorkedit.desktop", "applications/zorkview.desktop"]),
+    ] {
+        for file in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "[Desktop Entry]\nName=Zork\n").unwrap();
+        }
+    }
+    env::set_var("XDG_DATA_HOME", &user);
+    env::set_var("XDG_DATA_DIRS", &system);
+
+    assert_eq!(desktop::application_dirs(), [user.join("applications"), system.join("applications")]);
+
+    // The same ID, whether in a subdirectory or not
+    assert!(desktop::is_overridden(&system.join("applications/zorkmail.desktop")));
+    assert!(desktop::is_overridden(&system.join("applications/kde/zorkedit.desktop")));
+
+    assert!(!desktop::is_overridden(&system.join("applications/zorkview.desktop")));
+    assert!(!desktop::is_overridden(&user.join("applications/zorkmail.desktop")));
+    assert!(!desktop::is_overridden(&root.join("elsewhere/zorkmail.desktop")));
+
+    fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,19 @@
+-- With full-text search over file contents, before versioning.
+CREATE TABLE files (
+    name TEXT NOT NULL,
+    path TEXT PRIMARY KEY,
+    depth INTEGER NOT NULL,
+    executable BOOL NOT NULL,
+    desktop BOOL NOT NULL,
+    icon TEXT,
+    last_accessed INTEGER NOT NULL
+);
+
+CREATE VIRTUAL TABLE contents USING fts5(
+    path UNINDEXED,
+    body
+);
+
+INSERT INTO files VALUES ('notes.md', '/home/user/docs/notes.md', 2, 0, 0, NULL, 1700000000);
+INSERT INTO files VALUES ('Firefox', '/usr/share/applications/firefox.desktop', 3, 0, 1, 'firefox', 1700000000);
+INSERT INTO contents VALUES ('/home/user/docs/notes.md', 'the quick brown fox');
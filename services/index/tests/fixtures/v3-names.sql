@@ -0,0 +1,37 @@
+-- With the trigram index over paths, before versioning.
+CREATE TABLE files (
+    name TEXT NOT NULL,
+    path TEXT PRIMARY KEY,
+    depth INTEGER NOT NULL,
+    executable BOOL NOT NULL,
+    desktop BOOL NOT NULL,
+    icon TEXT,
+    last_accessed INTEGER NOT NULL
+);
+
+CREATE VIRTUAL TABLE contents USING fts5(
+    path UNINDEXED,
+    body
+);
+
+CREATE VIRTUAL TABLE names USING fts5(
+    path,
+    content = 'files',
+    content_rowid = 'rowid',
+    tokenize = 'trigram'
+);
+
+CREATE TRIGGER names_insert AFTER INSERT ON files BEGIN
+    INSERT INTO names (rowid, path) VALUES (new.rowid, new.path);
+END;
+CREATE TRIGGER names_delete AFTER DELETE ON files BEGIN
+    INSERT INTO names (names, rowid, path) VALUES ('delete', old.rowid, old.path);
+END;
+CREATE TRIGGER names_update AFTER UPDATE OF path ON files BEGIN
+    INSERT INTO names (names, rowid, path) VALUES ('delete', old.rowid, old.path);
+    INSERT INTO names (rowid, path) VALUES (new.rowid, new.path);
+END;
+
+INSERT INTO files VALUES ('notes.md', '/home/user/docs/notes.md', 2, 0, 0, NULL, 1700000000);
+INSERT INTO files VALUES ('Firefox', '/usr/share/applications/firefox.desktop', 3, 0, 1, 'firefox', 1700000000);
+INSERT INTO contents VALUES ('/home/user/docs/notes.md', 'the quick brown fox');
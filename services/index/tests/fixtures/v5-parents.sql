@@ -0,0 +1,50 @@
+-- With each file's directory, the last schema before versioning.
+CREATE TABLE files (
+    name TEXT NOT NULL,
+    path TEXT PRIMARY KEY,
+    parent TEXT NOT NULL DEFAULT '',
+    depth INTEGER NOT NULL,
+    executable BOOL NOT NULL,
+    desktop BOOL NOT NULL,
+    icon TEXT,
+    indexed_at INTEGER NOT NULL,
+    kind TEXT NOT NULL DEFAULT 'file',
+    size INTEGER NOT NULL DEFAULT 0,
+    modified INTEGER NOT NULL DEFAULT 0,
+    changed INTEGER NOT NULL DEFAULT 0,
+    inode INTEGER NOT NULL DEFAULT 0,
+    device INTEGER NOT NULL DEFAULT 0,
+    owner INTEGER NOT NULL DEFAULT 0,
+    mime TEXT
+);
+
+CREATE VIRTUAL TABLE contents USING fts5(
+    path UNINDEXED,
+    body
+);
+
+CREATE INDEX files_parent ON files (parent);
+
+CREATE VIRTUAL TABLE names USING fts5(
+    path,
+    content = 'files',
+    content_rowid = 'rowid',
+    tokenize = 'trigram'
+);
+
+CREATE TRIGGER names_insert AFTER INSERT ON files BEGIN
+    INSERT INTO names (rowid, path) VALUES (new.rowid, new.path);
+END;
+CREATE TRIGGER names_delete AFTER DELETE ON files BEGIN
+    INSERT INTO names (names, rowid, path) VALUES ('delete', old.rowid, old.path);
+END;
+CREATE TRIGGER names_update AFTER UPDATE OF path ON files BEGIN
+    INSERT INTO names (names, rowid, path) VALUES ('delete', old.rowid, old.path);
+    INSERT INTO names (rowid, path) VALUES (new.rowid, new.path);
+END;
+
+INSERT INTO files VALUES ('notes.md', '/home/user/docs/notes.md', '/home/user/docs', 2, 0, 0, NULL, 1700000000,
+    'file', 19, 1690000000, 1690000000, 12, 1, 1000, 'text/markdown');
+INSERT INTO files VALUES ('Firefox', '/usr/share/applications/firefox.desktop', '/usr/share/applications', 3, 0, 1, 'firefox',
+    1700000000, 'file', 300, 1690000000, 1690000000, 13, 1, 0, NULL);
+INSERT INTO contents VALUES ('/home/user/docs/notes.md', 'the quick brown fox');
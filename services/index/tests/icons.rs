@@ -0,0 +1,138 @@
+//! Looking icons up in themes, see `src/icons.rs`.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use image::GenericImageView;
+use index::icons;
+
+/// Themes, by their `index.theme` and the icons in them.
+const THEMES: [(&str, &str, &[&str]); 6] = [
+    ("hicolor", "Directories=48x48/apps\n\n[48x48/apps]\nSize=48\nType=Fixed\n", &["48x48/apps/base.png"]),
+    (
+        "Fixed",
+        "Directories=16x16/apps,48x48/apps\n\n[16x16/apps]\nSize=16\nType=Fixed\n\n[48x48/apps]\nSize=48\nType=Fixed\n",
+        &["16x16/apps/zap.png", "48x48/apps/zap.png"],
+    ),
+    (
+        "Scalable",
+        "Directories=16x16/apps,scalable/apps\n\n[16x16/apps]\nSize=16\nType=Fixed\n\n\
+         [scalable/apps]\nSize=48\nMinSize=8\nMaxSize=256\nType=Scalable\n",
+        &["16x16/apps/zap.png", "scalable/apps/zap.svg"],
+    ),
+    (
+        "Threshold",
+        "Directories=24x24/apps,32x32/apps\nScaledDirectories=32x32@2/apps\n\n[24x24/apps]\nSize=24\nType=Fixed\n\n\
+         [32x32/apps]\nSize=32\nThreshold=4\n\n[32x32@2/apps]\nSize=32\nScale=2\n",
+        &["24x24/apps/zap.png", "32x32/apps/zap.png", "32x32@2/apps/zap.png"],
+    ),
+    ("Child", "Inherits=Parent\nDirectories=apps\n\n[apps]\nSize=48\n", &["apps/own.png"]),
+    ("Parent", "Inherits=Child\nDirectories=apps\n\n[apps]\nSize=48\n", &["apps/inherited.png"]),
+];
+
+const SVG: &str = r#"<svg xmlns="http://www.w3.org/2000/svg" width="16" height="8"><rect width="16" height="8" fill="red"/></svg>"#;
+
+/// Where the themes are installed, with the environment pointing at them and nowhere else.
+static ICONS: LazyLock<PathBuf> = LazyLock::new(|| {
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("icons");
+    let _ = fs::remove_dir_all(&root);
+
+    let icons = root.join("data/icons");
+    for (theme, index, files) in THEMES {
+        write(&icons.join(theme).join("index.theme"), &format!("[Icon Theme]\nName={theme}\n{index}"));
+        for file in files {
+            write(&icons.join(theme).join(file), if file.ends_with(".svg") { SVG } else { "" });
+        }
+    }
+
+    env::set_var("HOME", &root);
+    env::set_var("XDG_DATA_HOME", root.join("data"));
+    env::set_var("XDG_DATA_DIRS", root.join("nowhere"));
+    icons
+});
+
+fn write(path: &Path, contents: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+/// Where `name` is found for `size` at `scale` in `theme`, relative to [`ICONS`].
+fn find(name: &str, theme: &str, size: u32, scale: u32) -> Option<String> {
+    let root = &*ICONS;
+    let found = icons::find(name, theme, size, scale)?;
+    Some(found.strip_prefix(root).unwrap().to_string_lossy().to_string())
+}
+
+#[test]
+fn finds_fixed_sizes() {
+    assert_eq!(find("zap", "Fixed", 16, 1).as_deref(), Some("Fixed/16x16/apps/zap.png"));
+    assert_eq!(find("zap", "Fixed", 48, 1).as_deref(), Some("Fixed/48x48/apps/zap.png"));
+
+    // Otherwise the closest
+    assert_eq!(find("zap", "Fixed", 20, 1).as_deref(), Some("Fixed/16x16/apps/zap.png"));
+    assert_eq!(find("zap", "Fixed", 40, 1).as_deref(), Some("Fixed/48x48/apps/zap.png"));
+    assert_eq!(find("zap", "Fixed", 256, 1).as_deref(), Some("Fixed/48x48/apps/zap.png"));
+}
+
+#[test]
+fn finds_scalable_sizes() {
+    assert_eq!(find("zap", "Scalable", 64, 1).as_deref(), Some("Scalable/scalable/apps/zap.svg"));
+    assert_eq!(find("zap", "Scalable", 8, 1).as_deref(), Some("Scalable/scalable/apps/zap.svg"));
+
+    // Directories made for the size come first, in the order the theme lists them
+    assert_eq!(find("zap", "Scalable", 16, 1).as_deref(), Some("Scalable/16x16/apps/zap.png"));
+
+    // Past its range, it's still the closest
+    assert_eq!(find("zap", "Scalable", 512, 1).as_deref(), Some("Scalable/scalable/apps/zap.svg"));
+    assert_eq!(find("zap", "Scalable", 4, 1).as_deref(), Some("Scalable/scalable/apps/zap.svg"));
+}
+
+#[test]
+fn finds_sizes_within_a_threshold() {
+    assert_eq!(find("zap", "Threshold", 28, 1).as_deref(), Some("Threshold/32x32/apps/zap.png"));
+    assert_eq!(find("zap", "Threshold", 36, 1).as_deref(), Some("Threshold/32x32/apps/zap.png"));
+    assert_eq!(find("zap", "Threshold", 24, 1).as_deref(), Some("Threshold/24x24/apps/zap.png"));
+
+    // Just outside it, whichever's closer
+    assert_eq!(find("zap", "Threshold", 27, 1).as_deref(), Some("Threshold/32x32/apps/zap.png"));
+    assert_eq!(find("zap", "Threshold", 25, 1).as_deref(), Some("Threshold/24x24/apps/zap.png"));
+
+    // Scaled directories are only for their scale
+    assert_eq!(find("zap", "Threshold", 32, 2).as_deref(), Some("Threshold/32x32@2/apps/zap.png"));
+}
+
+#[test]
+fn follows_inherited_themes_without_looping() {
+    assert_eq!(find("own", "Child", 48, 1).as_deref(), Some("Child/apps/own.png"));
+    assert_eq!(find("inherited", "Child", 48, 1).as_deref(), Some("Parent/apps/inherited.png"));
+    assert_eq!(find("own", "Parent", 48, 1).as_deref(), Some("Child/apps/own.png"));
+
+    // Child and Parent inherit from each other, then hicolor is the fallback
+    assert_eq!(find("base", "Child", 48, 1).as_deref(), Some("hicolor/48x48/apps/base.png"));
+    assert_eq!(find("missing", "Child", 48, 1), None);
+}
+
+#[test]
+fn looks_for_themes_installed_later() {
+    assert_eq!(find("late", "Late", 48, 1), None);
+
+    write(&ICONS.join("Late/index.theme"), "[Icon Theme]\nName=Late\nDirectories=apps\n\n[apps]\nSize=48\n");
+    write(&ICONS.join("Late/apps/late.png"), "");
+    assert_eq!(find("late", "Late", 48, 1).as_deref(), Some("Late/apps/late.png"));
+}
+
+#[test]
+fn finds_absolute_paths_as_is() {
+    let path = ICONS.join("Fixed/16x16/apps/zap.png");
+    assert_eq!(icons::find(&path.to_string_lossy(), "Child", 48, 1), Some(path.clone()));
+    assert_eq!(icons::find(&path.with_file_name("missing.png").to_string_lossy(), "Child", 48, 1), None);
+}
+
+#[test]
+fn renders_svgs_to_size() {
+    let icon = icons::load(&ICONS.join("Scalable/scalable/apps/zap.svg"), 64).unwrap();
+    assert_eq!(icon.dimensions(), (64, 32));
+    assert_eq!(icon.get_pixel(32, 16).0, [255, 0, 0, 255]);
+}
@@ -0,0 +1,44 @@
+//! Dropping entries no longer within a root, see `src/lib.rs`.
+
+use std::path::Path;
+
+use index::{schema, settings::{IndexConfig, Root}};
+use rusqlite::Connection;
+
+fn paths(conn: &Connection, table: &str) -> Vec<String> {
+    let mut stmt = conn.prepare(&format!("SELECT path FROM {table} ORDER BY path")).unwrap();
+    stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect()
+}
+
+#[test]
+fn prunes_removed_roots_and_lowered_depths() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    schema::migrate(&mut conn).unwrap();
+    for path in ["/kept/a", "/kept/a/b", "/kept/a/b/c", "/shallow/a", "/shallow/a/b", "/removed/a", "/keptx/a"] {
+        let parent = Path::new(path).parent().unwrap().to_string_lossy();
+        let name = Path::new(path).file_name().unwrap().to_string_lossy();
+        conn.execute(
+            "INSERT INTO files (name, path, parent, depth, indexed_at, executable, desktop)
+             VALUES (?1, ?2, ?3, 0, 0, 0, 0)",
+            (&name, path, &parent),
+        )
+        .unwrap();
+        conn.execute("INSERT INTO contents (path, body) VALUES (?1, 'body')", [path]).unwrap();
+    }
+
+    // `/kept` goes two directories deep, so its entries three levels in, and `/shallow` none
+    let config = IndexConfig {
+        roots: vec![Root::new("/kept"), Root { depth: Some(0), ..Root::new("/shallow/") }],
+        include_path: false,
+        max_depth: 2,
+        ..IndexConfig::default()
+    };
+    assert_eq!(index::prune(&mut conn, &config).unwrap(), 3);
+
+    let kept = ["/kept/a", "/kept/a/b", "/kept/a/b/c", "/shallow/a"];
+    assert_eq!(paths(&conn, "files"), kept);
+    assert_eq!(paths(&conn, "contents"), kept);
+
+    // Nothing more the second time round
+    assert_eq!(index::prune(&mut conn, &config).unwrap(), 0);
+}
@@ -0,0 +1,145 @@
+//! Parsing the search query language, see `src/query.rs`.
+
+use index::query::{Comparison, Filter, ParseError, Query, Sort, SortKey, Term, Type};
+
+fn parse(query: &str) -> Query { Query::parse(query).unwrap_or_else(|e| panic!("{query}: {e}")) }
+
+/// The only term of a one-term query.
+fn term(query: &str) -> Term {
+    match parse(query).clauses.as_slice() {
+        [clause] => match clause.as_slice() {
+            [term] => term.clone(),
+            terms => panic!("{query}: expected one term, got {terms:?}"),
+        },
+        clauses => panic!("{query}: expected one clause, got {clauses:?}"),
+    }
+}
+
+fn filter(query: &str) -> Filter { term(query).filter }
+
+fn error(query: &str) -> String {
+    match Query::parse(query) {
+        Ok(parsed) => panic!("{query}: expected an error, got {parsed:?}"),
+        Err(ParseError(message)) => message,
+    }
+}
+
+#[test]
+fn negates_terms() {
+    assert_eq!(term("-foo"), Term { negated: true, filter: Filter::Text("foo".into()) });
+    assert_eq!(term("-ext:rs"), Term { negated: true, filter: Filter::Ext(vec!["rs".into()]) });
+    assert_eq!(term("-\"foo bar\""), Term { negated: true, filter: Filter::Phrase("foo bar".into()) });
+
+    // A quoted `-` is part of the phrase
+    assert_eq!(term("\"-foo\""), Term { negated: false, filter: Filter::Phrase("-foo".into()) });
+
+    // Negated words aren't ones every match has to contain
+    assert_eq!(parse("main -test").words().collect::<Vec<_>>(), [&Filter::Text("main".into())]);
+}
+
+#[test]
+fn joins_terms_with_or() {
+    let query = parse("main ext:rs OR ext:toml OR -type:dir");
+    assert_eq!(query.clauses, [
+        vec![Term { negated: false, filter: Filter::Text("main".into()) }],
+        vec![
+            Term { negated: false, filter: Filter::Ext(vec!["rs".into()]) },
+            Term { negated: false, filter: Filter::Ext(vec!["toml".into()]) },
+            Term { negated: true, filter: Filter::Type(vec![Type::Dir]) },
+        ],
+    ]);
+
+    // Words in an `OR` don't all have to match, so aren't ranked by
+    let query = parse("foo OR bar baz");
+    assert_eq!(query.words().collect::<Vec<_>>(), [&Filter::Text("baz".into())]);
+    assert!(!query.is_plain());
+
+    // Only a bare `OR` joins
+    assert_eq!(parse("foo \"OR\" bar").clauses.len(), 3);
+    assert_eq!(parse("foo or bar").clauses.len(), 3);
+}
+
+#[test]
+fn parses_quoted_phrases() {
+    assert_eq!(filter("\"hello world\""), Filter::Phrase("hello world".into()));
+    assert_eq!(filter("\"ext:rs\""), Filter::Phrase("ext:rs".into()));
+
+    // Quotes in a filter's value keep its spaces
+    assert_eq!(filter("in:\"My Projects\""), Filter::In("My Projects".into()));
+
+    // But a key inside quotes isn't one
+    assert_eq!(filter("x\"a:b\""), Filter::Text("xa:b".into()));
+
+    assert!(parse("\"one\" two").is_plain());
+}
+
+#[test]
+fn parses_sizes() {
+    assert_eq!(filter("size:>10M"), Filter::Size(Comparison::Greater, 10 * 1024 * 1024));
+    assert_eq!(filter("size:<=1.5G"), Filter::Size(Comparison::LessOrEqual, 3 * 512 * 1024 * 1024));
+    assert_eq!(filter("size:>=2KiB"), Filter::Size(Comparison::GreaterOrEqual, 2048));
+    assert_eq!(filter("size:<1tb"), Filter::Size(Comparison::Less, 1024u64.pow(4)));
+    assert_eq!(filter("size:=512"), Filter::Size(Comparison::Equal, 512));
+}
+
+#[test]
+fn parses_ages() {
+    assert_eq!(filter("modified:<7d"), Filter::Modified(Comparison::Less, 7 * 24 * 60 * 60));
+    assert_eq!(filter("modified:>30m"), Filter::Modified(Comparison::Greater, 30 * 60));
+    assert_eq!(filter("modified:>30min"), Filter::Modified(Comparison::Greater, 30 * 60));
+    assert_eq!(filter("changed:>=2w"), Filter::Changed(Comparison::GreaterOrEqual, 2 * 7 * 24 * 60 * 60));
+    assert_eq!(filter("changed:<1.5h"), Filter::Changed(Comparison::Less, 90 * 60));
+    assert_eq!(filter("modified:<=1y"), Filter::Modified(Comparison::LessOrEqual, 365 * 24 * 60 * 60));
+}
+
+#[test]
+fn parses_sorts() {
+    assert_eq!(parse("sort:size").sort, Some(Sort { key: SortKey::Size, descending: true }));
+    assert_eq!(parse("sort:name").sort, Some(Sort { key: SortKey::Name, descending: false }));
+    assert_eq!(parse("foo sort:modified-asc").sort, Some(Sort { key: SortKey::Modified, descending: false }));
+    assert_eq!(parse("sort:path-desc foo").clauses.len(), 1);
+}
+
+#[test]
+fn leaves_other_colons_as_text() {
+    assert_eq!(filter("12:30"), Filter::Text("12:30".into()));
+    assert_eq!(filter("C++:"), Filter::Text("C++:".into()));
+
+    // Including keys that aren't a filter, or close to one
+    assert_eq!(filter("std::fs"), Filter::Text("std::fs".into()));
+    assert_eq!(filter("http://x"), Filter::Text("http://x".into()));
+    assert_eq!(filter("bogus:1"), Filter::Text("bogus:1".into()));
+    assert_eq!(filter("is:open"), Filter::Text("is:open".into()));
+}
+
+#[test]
+fn rejects_invalid_queries() {
+    for (query, message) in [
+        ("\"unterminated", "unterminated quote"),
+        ("OR foo", "`OR` needs a term"),
+        ("foo OR", "`OR` needs a term"),
+        ("foo OR OR bar", "`OR` needs a term"),
+        ("sort:name OR foo", "`OR` needs a term"),
+        ("-", "`-` needs a term"),
+        ("\"\"", "empty quotes"),
+        ("ext:", "`ext:` needs a value"),
+        ("ext:rs|", "`ext:` needs a value"),
+        ("type:socket", "unknown type `socket`"),
+        ("in:", "`in:` needs a directory"),
+        ("size:10M", "`size:` needs a comparison"),
+        ("size:>10Q", "unknown size unit"),
+        ("size:>M", "doesn't start with a number"),
+        ("modified:<7x", "unknown time unit"),
+        ("sort:owner", "can't sort by `owner`"),
+        ("sort:name sort:size", "`sort:` can only be given once"),
+        ("foo OR sort:name", "`sort:` can only be given once"),
+        ("-sort:name", "`sort:` can't be negated"),
+        ("exts:rs", "unknown filter `exts:`, did you mean `ext:`?"),
+        ("typ:dir", "unknown filter `typ:`"),
+        ("sise:>1M", "did you mean `size:`?"),
+        ("Modified:<1d", "did you mean `modified:`?"),
+    ] {
+        let error = error(query);
+        assert!(error.contains(message), "{query}: expected `{message}`, got `{error}`");
+    }
+}
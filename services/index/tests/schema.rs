@@ -0,0 +1,125 @@
+//! Migrating `index.db` from every schema released, see `tests/fixtures`.
+
+use index::schema::{self, SchemaError};
+use rusqlite::Connection;
+
+/// Every schema from before versioning, with the version it should be detected at.
+const FIXTURES: [(u32, &str); 5] = [
+    (1, include_str!("fixtures/v1-files.sql")),
+    (2, include_str!("fixtures/v2-contents.sql")),
+    (3, include_str!("fixtures/v3-names.sql")),
+    (4, include_str!("fixtures/v4-metadata.sql")),
+    (5, include_str!("fixtures/v5-parents.sql")),
+];
+
+fn fixture(sql: &str) -> Connection {
+    let conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(sql).unwrap();
+    conn
+}
+
+fn columns(conn: &Connection) -> Vec<String> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info('files') ORDER BY name").unwrap();
+    stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect()
+}
+
+/// Paths whose trigram index entry contains `needle`.
+fn names_matching(conn: &Connection, needle: &str) -> Vec<String> {
+    let mut stmt = conn
+        .prepare("SELECT files.path FROM names JOIN files ON files.rowid = names.rowid WHERE names MATCH ?1 ORDER BY 1")
+        .unwrap();
+    stmt.query_map([format!("\"{needle}\"")], |row| row.get(0)).unwrap().map(Result::unwrap).collect()
+}
+
+#[test]
+fn creates_an_empty_database() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    schema::migrate(&mut conn).unwrap();
+
+    assert_eq!(schema::version(&conn).unwrap(), schema::VERSION);
+
+    // The same as databases migrated from the first release
+    let mut migrated = fixture(FIXTURES[0].1);
+    schema::migrate(&mut migrated).unwrap();
+    assert_eq!(columns(&conn), columns(&migrated));
+}
+
+#[test]
+fn migrates_every_previous_schema() {
+    for (detected, sql) in FIXTURES {
+        let mut conn = fixture(sql);
+        schema::migrate(&mut conn).unwrap_or_else(|e| panic!("From version {detected}: {e}"));
+        assert_eq!(schema::version(&conn).unwrap(), schema::VERSION, "From version {detected}");
+
+        let columns = columns(&conn);
+        for column in ["indexed_at", "kind", "mime", "parent"] {
+            assert!(columns.iter().any(|c| c == column), "From version {detected}, missing {column}");
+        }
+        assert!(!columns.iter().any(|c| c == "last_accessed"), "From version {detected}");
+
+        // Rows survive, with what they gained filled in
+        let rows = conn
+            .query_row("SELECT count(*), min(indexed_at), min(kind) FROM files", [], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(2)?))
+            })
+            .unwrap();
+        assert_eq!(rows, (2, 1700000000, "file".to_string()), "From version {detected}");
+
+        let parent: String =
+            conn.query_row("SELECT parent FROM files WHERE name = 'notes.md'", [], |row| row.get(0)).unwrap();
+        assert_eq!(parent, "/home/user/docs", "From version {detected}");
+
+        assert_eq!(names_matching(&conn, "applications"), ["/usr/share/applications/firefox.desktop"]);
+        assert_eq!(names_matching(&conn, "docs/no"), ["/home/user/docs/notes.md"]);
+    }
+}
+
+#[test]
+fn keeps_contents() {
+    for (detected, sql) in FIXTURES.into_iter().skip(1) {
+        let mut conn = fixture(sql);
+        schema::migrate(&mut conn).unwrap();
+
+        let path: String =
+            conn.query_row("SELECT path FROM contents WHERE contents MATCH 'brown'", [], |row| row.get(0)).unwrap();
+        assert_eq!(path, "/home/user/docs/notes.md", "From version {detected}");
+    }
+}
+
+#[test]
+fn keeps_the_trigram_index_in_sync() {
+    for (detected, sql) in FIXTURES {
+        let mut conn = fixture(sql);
+        schema::migrate(&mut conn).unwrap();
+
+        conn.execute("UPDATE files SET path = '/home/user/archive/notes.md' WHERE name = 'notes.md'", []).unwrap();
+        assert_eq!(names_matching(&conn, "archive"), ["/home/user/archive/notes.md"], "From version {detected}");
+        assert!(names_matching(&conn, "docs/no").is_empty(), "From version {detected}");
+
+        conn.execute("DELETE FROM files WHERE desktop", []).unwrap();
+        assert!(names_matching(&conn, "applications").is_empty(), "From version {detected}");
+    }
+}
+
+#[test]
+fn is_idempotent() {
+    let mut conn = fixture(FIXTURES[2].1);
+    schema::migrate(&mut conn).unwrap();
+    schema::migrate(&mut conn).unwrap();
+
+    assert_eq!(schema::version(&conn).unwrap(), schema::VERSION);
+    let count: i64 = conn.query_row("SELECT count(*) FROM files", [], |row| row.get(0)).unwrap();
+    assert_eq!(count, 2);
+}
+
+#[test]
+fn refuses_newer_schemas() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    schema::migrate(&mut conn).unwrap();
+    conn.pragma_update(None, "user_version", schema::VERSION + 1).unwrap();
+
+    match schema::migrate(&mut conn) {
+        Err(SchemaError::TooNew(version)) => assert_eq!(version, schema::VERSION + 1),
+        other => panic!("Expected TooNew, got {other:?}"),
+    }
+}
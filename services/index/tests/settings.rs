@@ -0,0 +1,35 @@
+//! Upgrading `IndexService` config files, see `src/settings.rs`.
+
+use std::{env, fs, path::Path};
+
+use config::{migrate::VERSION_KEY, ty::App};
+use index::settings::{self, IndexConfig};
+
+const V0: &str = "# Mine\nmax_depth = 3\nreindex_threshold = 60\n";
+
+#[test]
+fn upgrades_unversioned_files_keeping_a_backup() {
+    let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("settings");
+    let _ = fs::remove_dir_all(&root);
+    env::set_var("HOME", &root);
+    env::set_var("XDG_CONFIG_HOME", &root);
+
+    let path = config::config_dir().unwrap().join("IndexService.toml");
+    fs::write(&path, V0).unwrap();
+
+    settings::register_migrations();
+    let config = config::get_config::<IndexConfig>(App::IndexService).unwrap();
+    assert_eq!(config.max_depth, 3);
+
+    let upgraded = fs::read_to_string(&path).unwrap();
+    assert!(upgraded.contains("# Mine"), "{upgraded}");
+    assert!(upgraded.contains(&format!("{VERSION_KEY} = 1")), "{upgraded}");
+    assert!(!upgraded.contains("reindex_threshold"), "{upgraded}");
+    assert_eq!(fs::read_to_string(path.with_file_name("IndexService.toml.v0.bak")).unwrap(), V0);
+
+    // Only once
+    fs::remove_file(path.with_file_name("IndexService.toml.v0.bak")).unwrap();
+    config::get_config::<IndexConfig>(App::IndexService).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), upgraded);
+    assert!(!path.with_file_name("IndexService.toml.v0.bak").exists());
+}